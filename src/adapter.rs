@@ -0,0 +1,417 @@
+//! Adapters for connections without a HAL implementing the embedded-hal traits
+
+use core::convert::Infallible;
+use embedded_hal::digital::v2::OutputPin;
+
+/// Pin which isn't connected, e.g. WAKE or RST on a USB-UART adapter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoPin;
+
+impl OutputPin for NoPin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+pub use self::serial::SerialAdapter;
+
+#[cfg(feature = "hal1")]
+pub use self::hal1::{DelayAdapter, EmbeddedIoAdapter, PinAdapter};
+
+#[cfg(feature = "std")]
+mod serial {
+    use embedded_hal::{blocking::serial::Write, serial::Read};
+    use std::io;
+
+    /// Serial port from anything implementing [io::Read] and [io::Write]
+    ///
+    /// Works with a `serialport::SerialPort`, a file of a tty or a socket. Reads which time out
+    /// are reported as [nb::Error::WouldBlock], so the driver treats them like a missing answer.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let port = serialport::new("/dev/ttyUSB0", 115_200)
+    ///     .timeout(Duration::from_secs(1))
+    ///     .open()?;
+    /// let mut serial = SerialAdapter::new(port);
+    /// let mut epd4in3 = EPD4in3::new(&mut serial, NoPin, NoPin, &mut delay)?;
+    /// ```
+    #[derive(Debug)]
+    pub struct SerialAdapter<T> {
+        inner: T,
+    }
+
+    impl<T> SerialAdapter<T> {
+        pub fn new(inner: T) -> Self {
+            SerialAdapter { inner }
+        }
+
+        pub fn get_ref(&self) -> &T {
+            &self.inner
+        }
+
+        pub fn get_mut(&mut self) -> &mut T {
+            &mut self.inner
+        }
+
+        pub fn into_inner(self) -> T {
+            self.inner
+        }
+    }
+
+    #[cfg(feature = "serialport")]
+    impl SerialAdapter<std::boxed::Box<dyn serialport::SerialPort>> {
+        /// Opens a serial port with 8N1 and the given read timeout
+        pub fn open(
+            path: &str,
+            baud: u32,
+            timeout: core::time::Duration,
+        ) -> serialport::Result<Self> {
+            let port = serialport::new(path, baud).timeout(timeout).open()?;
+            Ok(SerialAdapter::new(port))
+        }
+    }
+
+    impl<T: io::Write> Write<u8> for SerialAdapter<T> {
+        type Error = io::Error;
+
+        fn bwrite_all(&mut self, buffer: &[u8]) -> Result<(), Self::Error> {
+            self.inner.write_all(buffer)
+        }
+
+        fn bflush(&mut self) -> Result<(), Self::Error> {
+            self.inner.flush()
+        }
+    }
+
+    impl<T: io::Read> Read<u8> for SerialAdapter<T> {
+        type Error = io::Error;
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            let mut byte = [0];
+            match self.inner.read(&mut byte) {
+                Ok(1) => Ok(byte[0]),
+                Ok(_) => Err(nb::Error::Other(io::ErrorKind::UnexpectedEof.into())),
+                Err(e) => match e.kind() {
+                    io::ErrorKind::TimedOut
+                    | io::ErrorKind::WouldBlock
+                    | io::ErrorKind::Interrupted => Err(nb::Error::WouldBlock),
+                    _ => Err(nb::Error::Other(e)),
+                },
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        extern crate std;
+        use std::collections::VecDeque;
+        use std::vec;
+        use std::vec::Vec;
+
+        // answers reads from a list of results
+        struct Script(VecDeque<io::Result<u8>>);
+
+        impl io::Read for Script {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                match self.0.pop_front() {
+                    Some(Ok(byte)) => {
+                        buf[0] = byte;
+                        Ok(1)
+                    }
+                    Some(Err(e)) => Err(e),
+                    None => Ok(0),
+                }
+            }
+        }
+
+        #[test]
+        fn maps_read_errors() {
+            let mut serial = SerialAdapter::new(Script(VecDeque::from(vec![
+                Ok(b'O'),
+                Err(io::ErrorKind::TimedOut.into()),
+                Err(io::ErrorKind::BrokenPipe.into()),
+            ])));
+            assert_eq!(serial.read().unwrap(), b'O');
+            assert!(matches!(serial.read(), Err(nb::Error::WouldBlock)));
+            match serial.read() {
+                Err(nb::Error::Other(e)) => assert_eq!(e.kind(), io::ErrorKind::BrokenPipe),
+                _ => panic!("expected an error"),
+            }
+            match serial.read() {
+                Err(nb::Error::Other(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+                _ => panic!("expected an error"),
+            }
+        }
+
+        #[cfg(feature = "epd4in3")]
+        #[test]
+        fn writes_frames() {
+            use crate::command;
+
+            let mut serial = SerialAdapter::new(Vec::new());
+            command::write_refresh(&mut serial).unwrap();
+            assert_eq!(serial.into_inner(), command::refresh().unwrap().get_bytes());
+        }
+    }
+}
+
+#[cfg(feature = "hal1")]
+mod hal1 {
+    use embedded_hal::{
+        blocking::{delay::DelayMs, serial::Write},
+        serial::Read,
+    };
+    use embedded_hal_1::{delay::DelayNs, digital};
+    use embedded_io::ReadExactError;
+
+    /// Serial port from a HAL implementing [embedded_io::Read], [embedded_io::ReadReady] and
+    /// [embedded_io::Write]
+    ///
+    /// Reads return [nb::Error::WouldBlock] while [read_ready()](embedded_io::ReadReady::read_ready())
+    /// is false, like the serial ports of embedded-hal 0.2 HALs, so the ready timeout fires if
+    /// the module doesn't answer. An end of file is reported as [ReadExactError::UnexpectedEof].
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut serial = EmbeddedIoAdapter::new(uart);
+    /// let mut epd4in3 = EPD4in3::new(
+    ///     &mut serial,
+    ///     PinAdapter::new(wake),
+    ///     PinAdapter::new(rst),
+    ///     &mut DelayAdapter::new(delay),
+    /// )?;
+    /// ```
+    #[derive(Debug)]
+    pub struct EmbeddedIoAdapter<T> {
+        inner: T,
+    }
+
+    impl<T> EmbeddedIoAdapter<T> {
+        pub fn new(inner: T) -> Self {
+            EmbeddedIoAdapter { inner }
+        }
+
+        pub fn get_ref(&self) -> &T {
+            &self.inner
+        }
+
+        pub fn get_mut(&mut self) -> &mut T {
+            &mut self.inner
+        }
+
+        pub fn into_inner(self) -> T {
+            self.inner
+        }
+    }
+
+    impl<T: embedded_io::Write> Write<u8> for EmbeddedIoAdapter<T> {
+        type Error = T::Error;
+
+        fn bwrite_all(&mut self, buffer: &[u8]) -> Result<(), Self::Error> {
+            self.inner.write_all(buffer)
+        }
+
+        fn bflush(&mut self) -> Result<(), Self::Error> {
+            self.inner.flush()
+        }
+    }
+
+    impl<T: embedded_io::Read + embedded_io::ReadReady> Read<u8> for EmbeddedIoAdapter<T> {
+        type Error = ReadExactError<T::Error>;
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            let ready = self
+                .inner
+                .read_ready()
+                .map_err(|e| nb::Error::Other(ReadExactError::Other(e)))?;
+            if !ready {
+                return Err(nb::Error::WouldBlock);
+            }
+            let mut byte = [0];
+            self.inner.read_exact(&mut byte).map_err(nb::Error::Other)?;
+            Ok(byte[0])
+        }
+    }
+
+    /// Output pin from a HAL implementing [embedded_hal_1::digital::OutputPin]
+    #[derive(Debug)]
+    pub struct PinAdapter<P> {
+        inner: P,
+    }
+
+    impl<P> PinAdapter<P> {
+        pub fn new(inner: P) -> Self {
+            PinAdapter { inner }
+        }
+
+        pub fn into_inner(self) -> P {
+            self.inner
+        }
+    }
+
+    impl<P: digital::OutputPin> embedded_hal::digital::v2::OutputPin for PinAdapter<P> {
+        type Error = P::Error;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            self.inner.set_low()
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            self.inner.set_high()
+        }
+    }
+
+    /// Delay from a HAL implementing [embedded_hal_1::delay::DelayNs]
+    #[derive(Debug)]
+    pub struct DelayAdapter<D> {
+        inner: D,
+    }
+
+    impl<D> DelayAdapter<D> {
+        pub fn new(inner: D) -> Self {
+            DelayAdapter { inner }
+        }
+
+        pub fn into_inner(self) -> D {
+            self.inner
+        }
+    }
+
+    impl<D: DelayNs> DelayMs<u16> for DelayAdapter<D> {
+        fn delay_ms(&mut self, ms: u16) {
+            self.inner.delay_ms(u32::from(ms));
+        }
+    }
+
+    #[cfg(all(test, feature = "epd4in3"))]
+    mod tests {
+        use super::*;
+        use crate::epd4in3::{command, EPD4in3, Op};
+        use crate::prelude::WaveshareDisplay;
+        use embedded_hal_mock::eh1::delay::{CheckedDelay, Transaction as DelayTransaction};
+        use embedded_hal_mock::eh1::digital::{Mock as PinMock, State, Transaction};
+        extern crate std;
+        use std::collections::VecDeque;
+        use std::vec::Vec;
+
+        // embedded-io port recording writes and answering reads from a list
+        //
+        // Once the list is empty it is never ready, or at the end of file if `eof` is set
+        #[derive(Default)]
+        struct MockIo {
+            written: Vec<u8>,
+            replies: VecDeque<u8>,
+            eof: bool,
+        }
+
+        impl embedded_io::ErrorType for MockIo {
+            type Error = embedded_io::ErrorKind;
+        }
+
+        impl embedded_io::Read for MockIo {
+            fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+                match self.replies.pop_front() {
+                    Some(byte) => {
+                        buf[0] = byte;
+                        Ok(1)
+                    }
+                    None => Ok(0),
+                }
+            }
+        }
+
+        impl embedded_io::ReadReady for MockIo {
+            fn read_ready(&mut self) -> Result<bool, Self::Error> {
+                Ok(self.eof || !self.replies.is_empty())
+            }
+        }
+
+        impl embedded_io::Write for MockIo {
+            fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+                self.written.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> Result<(), Self::Error> {
+                Ok(())
+            }
+        }
+
+        fn pulse() -> [Transaction; 3] {
+            [
+                Transaction::set(State::Low),
+                Transaction::set(State::High),
+                Transaction::set(State::Low),
+            ]
+        }
+
+        #[test]
+        fn drives_hal1_peripherals() {
+            let mut io = MockIo::default();
+            io.replies.extend(b"OKOKOK".iter());
+            let mut serial = EmbeddedIoAdapter::new(io);
+            let mut wake = PinMock::new(&pulse());
+            let mut rst = PinMock::new(&pulse());
+            let mut delay = CheckedDelay::new(&[
+                DelayTransaction::delay_ms(10),
+                DelayTransaction::delay_ms(10),
+                DelayTransaction::delay_ms(10),
+                DelayTransaction::delay_ms(10),
+            ]);
+
+            let mut epd = EPD4in3::new(
+                &mut serial,
+                PinAdapter::new(wake.clone()),
+                PinAdapter::new(rst.clone()),
+                &mut DelayAdapter::new(&mut delay),
+            )
+            .unwrap();
+            epd.wake_up(&mut serial, &mut DelayAdapter::new(&mut delay))
+                .unwrap();
+            epd.draw_ops(&mut serial, &[Op::Clear]).unwrap();
+
+            let handshake = command::handshake().unwrap();
+            let mut expected = [handshake.get_bytes(); 2].concat();
+            expected.extend_from_slice(command::clear().unwrap().get_bytes());
+            assert_eq!(serial.get_ref().written, expected);
+            wake.done();
+            rst.done();
+            delay.done();
+
+            // nothing left to read
+            assert!(matches!(serial.read(), Err(nb::Error::WouldBlock)));
+            serial.get_mut().eof = true;
+            match serial.read() {
+                Err(nb::Error::Other(ReadExactError::UnexpectedEof)) => {}
+                _ => panic!("expected the end of the input"),
+            }
+        }
+
+        #[test]
+        fn times_out_without_answers() {
+            use crate::epd4in3::EPD4in3Builder;
+            use crate::traits::Error;
+            use embedded_hal_mock::eh1::delay::NoopDelay;
+
+            let mut serial = EmbeddedIoAdapter::new(MockIo::default());
+            let result = EPD4in3Builder::new()
+                .ready_timeout(100)
+                .build(&mut serial, &mut DelayAdapter::new(NoopDelay::new()));
+            match result {
+                Err(Error::Timeout) => {}
+                _ => panic!("expected a timeout"),
+            }
+        }
+    }
+}
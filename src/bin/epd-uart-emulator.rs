@@ -0,0 +1,402 @@
+//! Emulates a 4.3 inch e-paper module on a pseudo terminal
+//!
+//! ```text
+//! epd-uart-emulator [--png <file>] [--terminal]
+//! ```
+//!
+//! Prints the path of the pseudo terminal, which can be opened like the serial port of a
+//! real module. Every frame is answered with `OK`, or with `Error` if it is broken or unknown.
+//! The screen is written to the png file and/or shown in the terminal on every refresh.
+//! Text and bitmaps aren't drawn, they are only logged.
+
+use std::env;
+use std::ffi::CStr;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::process;
+
+use epd_waveshare_uart::{
+    color::EpdColor,
+    command::{Command, Fontsize, Rotation, Storage},
+    epd4in3::{Op, Raster, HEIGHT, WIDTH},
+};
+
+const USAGE: &str = "usage: epd-uart-emulator [--png <file>] [--terminal]";
+
+// size of the terminal preview in pixels per character
+const CELL_WIDTH: usize = 8;
+const CELL_HEIGHT: usize = 16;
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("epd-uart-emulator: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut png = None;
+    let mut terminal = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--png" => png = Some(args.next().ok_or(USAGE)?),
+            "--terminal" => terminal = true,
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+    let terminal = terminal || png.is_none();
+
+    let (mut master, path) = open_pty().map_err(|e| format!("can't open a pty: {}", e))?;
+    println!("{}", path);
+
+    let mut pixels = vec![EpdColor::White; (WIDTH * HEIGHT) as usize];
+    let mut module = Module {
+        raster: Raster::new(WIDTH as u16, HEIGHT as u16, &mut pixels),
+        rotation: Rotation::Rotation0,
+    };
+    let mut decoder = Decoder::default();
+    let mut buffer = [0; 4096];
+    loop {
+        let len = master.read(&mut buffer).map_err(|e| e.to_string())?;
+        for &byte in buffer[..len].iter() {
+            let reply: &[u8] = match decoder.push(byte) {
+                None => continue,
+                Some(Ok((cmd, payload))) => match module.handle(cmd, &payload) {
+                    Ok(refresh) => {
+                        if refresh {
+                            module.show(png.as_deref(), terminal)?;
+                        }
+                        b"OK"
+                    }
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        b"Error"
+                    }
+                },
+                Some(Err(e)) => {
+                    eprintln!("broken frame: {}", e);
+                    b"Error"
+                }
+            };
+            master.write_all(reply).map_err(|e| e.to_string())?;
+        }
+    }
+}
+
+/// Opens a pseudo terminal in raw mode and returns its master and the path of the slave
+fn open_pty() -> io::Result<(File, String)> {
+    unsafe {
+        let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        if master < 0 || libc::grantpt(master) != 0 || libc::unlockpt(master) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut name = [0 as libc::c_char; 128];
+        if libc::ptsname_r(master, name.as_mut_ptr(), name.len()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let path = CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned();
+
+        // keep the slave open, otherwise reading the master fails while no client is connected
+        let slave = libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY);
+        if slave < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut termios = std::mem::zeroed();
+        if libc::tcgetattr(slave, &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        if libc::tcsetattr(slave, libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok((File::from_raw_fd(master), path))
+    }
+}
+
+/// Splits a stream of bytes into frames
+#[derive(Default)]
+struct Decoder {
+    frame: Vec<u8>,
+}
+
+impl Decoder {
+    /// Adds a byte, returns the command and payload once a frame is complete
+    fn push(&mut self, byte: u8) -> Option<Result<(u8, Vec<u8>), &'static str>> {
+        if self.frame.is_empty() && byte != 0xA5 {
+            // garbage between frames
+            return None;
+        }
+        self.frame.push(byte);
+        if self.frame.len() < 3 {
+            return None;
+        }
+        let len = usize::from(u16::from_be_bytes([self.frame[1], self.frame[2]]));
+        if !(9..=1033).contains(&len) {
+            self.frame.clear();
+            return Some(Err("invalid length"));
+        }
+        if self.frame.len() < len {
+            return None;
+        }
+
+        let frame = std::mem::take(&mut self.frame);
+        if frame[len - 5..len - 1] != [0xCC, 0x33, 0xC3, 0x3C] {
+            return Some(Err("missing end of frame"));
+        }
+        if frame.iter().fold(0, |parity, byte| parity ^ byte) != 0 {
+            return Some(Err("wrong parity"));
+        }
+        Some(Ok((frame[3], frame[4..len - 5].to_vec())))
+    }
+}
+
+struct Module<'a> {
+    raster: Raster<'a>,
+    rotation: Rotation,
+}
+
+impl<'a> Module<'a> {
+    /// Executes a frame, returns if the screen has to be shown
+    fn handle(&mut self, cmd: u8, payload: &[u8]) -> Result<bool, String> {
+        let cmd = Command::from_address(cmd).ok_or(format!("unknown command {:#04x}", cmd))?;
+        let n = |i: usize| -> Result<u16, String> {
+            payload
+                .get(2 * i..2 * i + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .ok_or(format!("{:?}: payload too short", cmd))
+        };
+        let byte = |i: usize| -> Result<u8, String> {
+            payload
+                .get(i)
+                .copied()
+                .ok_or(format!("{:?}: payload too short", cmd))
+        };
+        let string = || {
+            let bytes = payload.get(4..).unwrap_or(&[]);
+            let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+            encoding_rs::GBK.decode(bytes).0.into_owned()
+        };
+        let color = |i: usize| -> Result<EpdColor, String> {
+            match byte(i)? {
+                value @ 0..=3 => Ok(EpdColor::from(value)),
+                value => Err(format!("invalid color {}", value)),
+            }
+        };
+
+        let op = match cmd {
+            Command::Handshake => Op::Handshake,
+            Command::SetBaud => Op::SetBaud(u32::from(n(0)?) << 16 | u32::from(n(1)?)),
+            Command::SetStorage => Op::SetStorage(match byte(0)? {
+                0 => Storage::Nand,
+                _ => Storage::MicroSd,
+            }),
+            Command::LoadFont => Op::LoadFont,
+            Command::LoadBmp => Op::LoadBmp,
+            Command::Clear => Op::Clear,
+            Command::Update => Op::Refresh,
+            Command::Sleep => Op::Sleep,
+            Command::SetRotation => Op::SetRotation(match byte(0)? {
+                0 => Rotation::Rotation0,
+                _ => Rotation::Rotation180,
+            }),
+            Command::SetColor => Op::SetColor(color(0)?, color(1)?),
+            Command::SetFontSizeEn | Command::SetFontSizeZh => {
+                let size = match byte(0)? {
+                    1 => Fontsize::Size32,
+                    2 => Fontsize::Size48,
+                    _ => Fontsize::Size64,
+                };
+                if cmd == Command::SetFontSizeEn {
+                    Op::SetFontSizeEn(size)
+                } else {
+                    Op::SetFontSizeZh(size)
+                }
+            }
+            Command::Point => Op::Point(n(0)?, n(1)?),
+            Command::Line => Op::Line(n(0)?, n(1)?, n(2)?, n(3)?),
+            Command::Rect => Op::Rect(n(0)?, n(1)?, n(2)?, n(3)?),
+            Command::FillRect => Op::FillRect(n(0)?, n(1)?, n(2)?, n(3)?),
+            Command::Circle => Op::Circle(n(0)?, n(1)?, n(2)?),
+            Command::FillCircle => Op::FillCircle(n(0)?, n(1)?, n(2)?),
+            Command::Tri => Op::Tri(n(0)?, n(1)?, n(2)?, n(3)?, n(4)?, n(5)?),
+            Command::FillTri => Op::FillTri(n(0)?, n(1)?, n(2)?, n(3)?, n(4)?, n(5)?),
+            Command::Text | Command::Bmp => {
+                eprintln!("{:?} at {}, {}: {}", cmd, n(0)?, n(1)?, string());
+                return Ok(false);
+            }
+        };
+
+        match op {
+            Op::SetRotation(rotation) => self.rotation = rotation,
+            Op::Sleep => eprintln!("sleeping, the wake up pin isn't emulated"),
+            _ => {}
+        }
+        self.raster.draw(&rotate(op, self.rotation));
+        Ok(op == Op::Refresh)
+    }
+
+    fn show(&self, png: Option<&str>, terminal: bool) -> Result<(), String> {
+        if let Some(path) = png {
+            write_png(path, self.raster.buffer())
+                .map_err(|e| format!("can't write {}: {}", path, e))?;
+        }
+        if terminal {
+            print!("{}", preview(self.raster.buffer()));
+        }
+        Ok(())
+    }
+}
+
+/// Applies the rotation of the module to the coordinates of a shape
+fn rotate(op: Op, rotation: Rotation) -> Op {
+    if rotation == Rotation::Rotation0 {
+        return op;
+    }
+    let x = |x: u16| (WIDTH as u16 - 1).saturating_sub(x);
+    let y = |y: u16| (HEIGHT as u16 - 1).saturating_sub(y);
+    match op {
+        Op::Point(x0, y0) => Op::Point(x(x0), y(y0)),
+        Op::Line(x0, y0, x1, y1) => Op::Line(x(x0), y(y0), x(x1), y(y1)),
+        Op::Rect(x0, y0, x1, y1) => Op::Rect(x(x0), y(y0), x(x1), y(y1)),
+        Op::FillRect(x0, y0, x1, y1) => Op::FillRect(x(x0), y(y0), x(x1), y(y1)),
+        Op::Circle(x0, y0, r) => Op::Circle(x(x0), y(y0), r),
+        Op::FillCircle(x0, y0, r) => Op::FillCircle(x(x0), y(y0), r),
+        Op::Tri(x0, y0, x1, y1, x2, y2) => Op::Tri(x(x0), y(y0), x(x1), y(y1), x(x2), y(y2)),
+        Op::FillTri(x0, y0, x1, y1, x2, y2) => {
+            Op::FillTri(x(x0), y(y0), x(x1), y(y1), x(x2), y(y2))
+        }
+        op => op,
+    }
+}
+
+// the four pixels of a packed byte happen to be evenly spaced gray levels
+fn gray(color: EpdColor) -> u8 {
+    color.get_byte_value()
+}
+
+fn write_png(path: &str, pixels: &[EpdColor]) -> io::Result<()> {
+    // write to a temporary file first, so viewers never see half a picture
+    let tmp = format!("{}.tmp", path);
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(&tmp)?), WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = pixels.iter().map(|&color| gray(color)).collect();
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .map_err(io::Error::other)?;
+    fs::rename(tmp, path)
+}
+
+/// Draws the screen with one character per cell of pixels
+fn preview(pixels: &[EpdColor]) -> String {
+    let (width, height) = (WIDTH as usize, HEIGHT as usize);
+    let columns = width / CELL_WIDTH;
+    let border = format!("+{}+\n", "-".repeat(columns));
+    let mut preview = border.clone();
+    for row in (0..height).step_by(CELL_HEIGHT) {
+        preview.push('|');
+        for column in 0..columns {
+            let mut sum = 0;
+            let mut count = 0;
+            for y in row..(row + CELL_HEIGHT).min(height) {
+                for x in column * CELL_WIDTH..(column + 1) * CELL_WIDTH {
+                    sum += u32::from(gray(pixels[y * width + x]));
+                    count += 1;
+                }
+            }
+            preview.push(match sum / count {
+                0..=42 => '@',
+                43..=127 => '#',
+                128..=212 => '+',
+                _ => ' ',
+            });
+        }
+        preview.push_str("|\n");
+    }
+    preview.push_str(&border);
+    preview
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use epd_waveshare_uart::command;
+
+    fn decode(bytes: &[u8]) -> Vec<Result<(u8, Vec<u8>), &'static str>> {
+        let mut decoder = Decoder::default();
+        bytes
+            .iter()
+            .filter_map(|&byte| decoder.push(byte))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_frames() {
+        let mut bytes = vec![0x00, 0x42];
+        bytes.extend_from_slice(command::point(1, 2).unwrap().get_bytes());
+        bytes.extend_from_slice(command::refresh().unwrap().get_bytes());
+        assert_eq!(
+            decode(&bytes),
+            [Ok((0x20, vec![0, 1, 0, 2])), Ok((0x0a, vec![]))]
+        );
+    }
+
+    #[test]
+    fn rejects_broken_frames() {
+        let mut bytes = command::clear().unwrap().get_bytes().to_vec();
+        bytes[8] ^= 1;
+        bytes.extend_from_slice(&[0xA5, 0x00, 0x02]);
+        bytes.extend_from_slice(command::clear().unwrap().get_bytes());
+        assert_eq!(
+            decode(&bytes),
+            [
+                Err("wrong parity"),
+                Err("invalid length"),
+                Ok((0x2e, vec![]))
+            ]
+        );
+    }
+
+    #[test]
+    fn draws_frames() {
+        let mut pixels = vec![EpdColor::White; (WIDTH * HEIGHT) as usize];
+        let mut module = Module {
+            raster: Raster::new(WIDTH as u16, HEIGHT as u16, &mut pixels),
+            rotation: Rotation::Rotation0,
+        };
+        let mut frames = vec![
+            command::set_rotation(Rotation::Rotation180).unwrap(),
+            command::set_color(EpdColor::Gray, EpdColor::White).unwrap(),
+            command::point(0, 0).unwrap(),
+            command::text(0, 0, "ignored").unwrap(),
+        ];
+        frames.push(command::refresh().unwrap());
+        let mut refreshed = Vec::new();
+        for frame in frames.iter() {
+            let (cmd, payload) = decode(frame.get_bytes()).remove(0).unwrap();
+            refreshed.push(module.handle(cmd, &payload).unwrap());
+        }
+        assert_eq!(refreshed, [false, false, false, false, true]);
+        assert_eq!(module.raster.buffer()[0], EpdColor::White);
+        assert_eq!(*module.raster.buffer().last().unwrap(), EpdColor::Gray);
+        assert!(module.handle(0x55, &[]).is_err());
+        assert!(module.handle(0x10, &[7, 0]).is_err());
+    }
+
+    #[test]
+    fn previews_screen() {
+        let mut pixels = vec![EpdColor::White; (WIDTH * HEIGHT) as usize];
+        for pixel in pixels[..WIDTH as usize * CELL_HEIGHT].iter_mut() {
+            *pixel = EpdColor::Black;
+        }
+        let preview = preview(&pixels);
+        let lines: Vec<&str> = preview.lines().collect();
+        assert_eq!(lines.len(), 2 + 38);
+        assert_eq!(lines[1], format!("|{}|", "@".repeat(100)));
+        assert_eq!(lines[2], format!("|{}|", " ".repeat(100)));
+    }
+}
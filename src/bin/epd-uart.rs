@@ -0,0 +1,349 @@
+//! Drives a 4.3 inch e-paper module connected to a serial port
+//!
+//! ```text
+//! epd-uart [--baud <baud>] <port> <command> [<args>...]
+//! ```
+//!
+//! WAKE and RST aren't used, so the module has to be awake already. Before drawing it has to
+//! answer a handshake.
+
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read as _};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use embedded_hal::blocking::delay::DelayMs;
+use epd_waveshare_uart::{
+    adapter::{NoPin, SerialAdapter},
+    epd4in3::{script::Script, CostCounter, EPD4in3, EPD4in3Builder, Op, HEIGHT, WIDTH},
+    prelude::*,
+};
+use serialport::SerialPort;
+
+const USAGE: &str = "usage: epd-uart [--baud <baud>] <port> <command> [<args>...]
+
+commands:
+    handshake
+    clear
+    refresh
+    sleep
+    text <x> <y> <text>
+    line <x0> <y0> <x1> <y1>
+    rect [--fill] <x0> <y0> <x1> <y1>
+    circle [--fill] <x> <y> <r>
+    bmp <x> <y> <filename>
+    set-baud <baud>
+    storage nand|sd
+    upload-image <file.png>
+    run-script [--optimize] <file>";
+
+const DEFAULT_BAUD: u32 = 115_200;
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+type CliResult<T> = Result<T, String>;
+
+type Port = SerialAdapter<Box<dyn SerialPort>>;
+
+/// Pauses the thread, e.g. between handshakes while the module is busy
+struct Sleep;
+
+impl DelayMs<u16> for Sleep {
+    fn delay_ms(&mut self, ms: u16) {
+        thread::sleep(Duration::from_millis(u64::from(ms)));
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("epd-uart: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> CliResult<()> {
+    let mut args = args.iter().map(String::as_str);
+    let mut baud = DEFAULT_BAUD;
+    let mut port = args.next();
+    if port == Some("--baud") {
+        baud = parse(args.next(), "baud rate")?;
+        port = args.next();
+    }
+    let (port, command) = match (port, args.next()) {
+        (Some(port), Some(command)) if !port.starts_with('-') => (port, command),
+        _ => return Err(USAGE.to_string()),
+    };
+    let args: Vec<&str> = args.collect();
+
+    let mut serial = SerialAdapter::open(port, baud, TIMEOUT)
+        .map_err(|e| format!("can't open {}: {}", port, e))?;
+
+    match command {
+        "handshake" | "set-baud" | "storage" => {
+            let line = script_line(command, &args)?;
+            let reply = exchange(&mut serial, parse_op(&line)?)?;
+            println!("{}", reply);
+            if command == "set-baud" {
+                println!("reconnect with --baud {}", args[0]);
+            }
+            Ok(())
+        }
+        "upload-image" => match args.as_slice() {
+            [path] => upload_image(&mut serial, path),
+            _ => Err(USAGE.to_string()),
+        },
+        "run-script" => match args.as_slice() {
+            [path] => run_script(&mut serial, path, false),
+            ["--optimize", path] => run_script(&mut serial, path, true),
+            _ => Err(USAGE.to_string()),
+        },
+        "sleep" if args.is_empty() => {
+            let epd = connect(&mut serial)?;
+            epd.sleep(&mut serial)
+                .map(drop)
+                .map_err(|(_, e)| device_error(e))
+        }
+        _ => {
+            let line = script_line(command, &args)?;
+            let op = parse_op(&line)?;
+            let mut epd = connect(&mut serial)?;
+            epd.draw_ops(&mut serial, &[op]).map_err(device_error)
+        }
+    }
+}
+
+fn parse<T: std::str::FromStr>(arg: Option<&str>, what: &str) -> CliResult<T> {
+    let arg = arg.ok_or_else(|| format!("missing {}", what))?;
+    arg.parse()
+        .map_err(|_| format!("invalid {}: {}", what, arg))
+}
+
+fn device_error<E: std::fmt::Debug>(e: E) -> String {
+    format!("device error: {:?}", e)
+}
+
+/// Turns a command and its arguments into a line of a script
+fn script_line(command: &str, args: &[&str]) -> CliResult<String> {
+    let (fill, args) = match args.split_first() {
+        Some((&"--fill", args)) => (true, args),
+        _ => (false, args),
+    };
+    let mut line = match (command, fill) {
+        ("rect", true) => "fill_rect",
+        ("circle", true) => "fill_circle",
+        ("set-baud", false) => "baud",
+        ("handshake", false)
+        | ("clear", false)
+        | ("refresh", false)
+        | ("sleep", false)
+        | ("text", false)
+        | ("line", false)
+        | ("rect", false)
+        | ("circle", false)
+        | ("bmp", false)
+        | ("storage", false) => command,
+        _ => return Err(format!("unknown command: {}\n\n{}", command, USAGE)),
+    }
+    .to_string();
+
+    for arg in args.iter() {
+        if arg.contains('"') {
+            return Err(format!("double quotes aren't supported: {}", arg));
+        }
+        line.push(' ');
+        if arg.is_empty() || arg.contains(char::is_whitespace) || arg.starts_with('#') {
+            line.push('"');
+            line.push_str(arg);
+            line.push('"');
+        } else {
+            line.push_str(arg);
+        }
+    }
+    Ok(line)
+}
+
+fn parse_op(line: &str) -> CliResult<Op<'_>> {
+    let mut script = Script::new(line);
+    let op = script
+        .next()
+        .ok_or_else(|| USAGE.to_string())?
+        .map_err(|e| format!("{}: {}", line, e.kind))?;
+    op.frame().map_err(|e| format!("{}: {:?}", line, e))?;
+    Ok(op)
+}
+
+fn connect(serial: &mut Port) -> CliResult<EPD4in3<Port, NoPin, NoPin>> {
+    // each unanswered handshake already waits for the read timeout, one is enough
+    EPD4in3Builder::new()
+        .ready_timeout(0)
+        .build(serial, &mut Sleep)
+        .map_err(device_error)
+}
+
+/// Sends a single frame and returns the reply of the module
+fn exchange(serial: &mut Port, op: Op) -> CliResult<String> {
+    op.write(serial).map_err(device_error)?;
+    let mut reply = Vec::new();
+    let mut byte = [0];
+    while !reply.ends_with(b"OK") && !reply.ends_with(b"Error") {
+        match serial.get_mut().read(&mut byte) {
+            Ok(0) => break,
+            Ok(_) => reply.push(byte[0]),
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => break,
+            Err(e) => return Err(device_error(e)),
+        }
+    }
+    if reply.is_empty() {
+        return Err("no reply from the module".to_string());
+    }
+    Ok(String::from_utf8_lossy(&reply).into_owned())
+}
+
+fn upload_image(serial: &mut Port, path: &str) -> CliResult<()> {
+    let buffer = load_image(path)?;
+    let mut epd = connect(serial)?;
+
+    let mut counter = CostCounter::new();
+    epd.dry_run()
+        .update_frame_auto_background(&mut counter, &buffer, &mut Sleep)
+        .map_err(device_error)?;
+    let cost = counter.cost();
+    let baud = serial.get_ref().baud_rate().unwrap_or(DEFAULT_BAUD);
+    let estimate = match cost.duration_ms(baud) {
+        Some(ms) => format!(", about {} s", ms / 1000),
+        None => String::new(),
+    };
+    println!(
+        "sending {} frames ({} bytes){}",
+        cost.total_frames(),
+        cost.bytes_written,
+        estimate
+    );
+
+    epd.update_frame_auto_background(serial, &buffer, &mut Sleep)
+        .map_err(device_error)?;
+    epd.display_frame(serial).map_err(device_error)
+}
+
+/// Loads a png into a frame buffer, smaller images are put into the top left corner
+fn load_image(path: &str) -> CliResult<Vec<EpdColor>> {
+    let file = File::open(path).map_err(|e| format!("can't open {}: {}", path, e))?;
+    let mut decoder = png::Decoder::new(file);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let error = |e: png::DecodingError| format!("can't decode {}: {}", path, e);
+    let mut reader = decoder.read_info().map_err(error)?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(error)?;
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    if width > WIDTH as usize || height > HEIGHT as usize {
+        return Err(format!(
+            "{} is {}x{}, at most {}x{} fit",
+            path, width, height, WIDTH, HEIGHT
+        ));
+    }
+    let channels = info.color_type.samples();
+    let mut buffer = vec![EpdColor::White; (WIDTH * HEIGHT) as usize];
+    for y in 0..height {
+        let row = &pixels[y * info.line_size..][..width * channels];
+        for (x, pixel) in row.chunks_exact(channels).enumerate() {
+            buffer[y * WIDTH as usize + x] = quantize(pixel);
+        }
+    }
+    Ok(buffer)
+}
+
+/// Maps a gray, gray + alpha, rgb or rgba pixel with 8 bits per sample to the nearest color
+fn quantize(pixel: &[u8]) -> EpdColor {
+    let (luma, alpha) = match *pixel {
+        [gray] => (u32::from(gray), 255),
+        [gray, alpha] => (u32::from(gray), u32::from(alpha)),
+        [r, g, b] => (luma(r, g, b), 255),
+        [r, g, b, alpha] => (luma(r, g, b), u32::from(alpha)),
+        _ => (255, 0),
+    };
+    // transparent parts are white
+    let luma = (luma * alpha + 255 * (255 - alpha)) / 255;
+    match luma {
+        0..=63 => EpdColor::Black,
+        64..=127 => EpdColor::DarkGray,
+        128..=191 => EpdColor::Gray,
+        _ => EpdColor::White,
+    }
+}
+
+fn luma(r: u8, g: u8, b: u8) -> u32 {
+    (299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)) / 1000
+}
+
+fn run_script(serial: &mut Port, path: &str, optimize: bool) -> CliResult<()> {
+    let script = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+    let errors: Vec<String> = Script::new(&script)
+        .frames()
+        .filter_map(Result::err)
+        .map(|e| format!("{}:{}: {}", path, e.line, e.kind))
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+
+    let mut ops: Vec<Op> = Script::new(&script).filter_map(Result::ok).collect();
+    if optimize {
+        let len = epd_waveshare_uart::epd4in3::optimize(&mut ops);
+        ops.truncate(len);
+    }
+    // a script may end with putting the display to sleep, which needs the sleeping handle
+    let sleep = ops.last() == Some(&Op::Sleep);
+    if sleep {
+        ops.pop();
+    }
+    let mut epd = connect(serial)?;
+    epd.draw_ops(serial, &ops).map_err(device_error)?;
+    if sleep {
+        epd.sleep(serial)
+            .map(drop)
+            .map_err(|(_, e)| device_error(e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_script_lines() {
+        assert_eq!(script_line("clear", &[]).unwrap(), "clear");
+        assert_eq!(
+            script_line("rect", &["--fill", "1", "2", "3", "4"]).unwrap(),
+            "fill_rect 1 2 3 4"
+        );
+        assert_eq!(
+            script_line("text", &["0", "0", "Hello World"]).unwrap(),
+            "text 0 0 \"Hello World\""
+        );
+        assert_eq!(script_line("set-baud", &["9600"]).unwrap(), "baud 9600");
+        assert!(script_line("line", &["--fill"]).is_err());
+        assert!(script_line("text", &["0", "0", "\"quoted\""]).is_err());
+    }
+
+    #[test]
+    fn parses_ops() {
+        assert_eq!(
+            parse_op("fill_circle 1 2 3").unwrap(),
+            Op::FillCircle(1, 2, 3)
+        );
+        assert!(parse_op("line 1 2").is_err());
+        assert!(parse_op("bmp 0 0 FILENAME_TOO_LONG.BMP").is_err());
+    }
+
+    #[test]
+    fn quantizes_pixels() {
+        assert_eq!(quantize(&[0]), EpdColor::Black);
+        assert_eq!(quantize(&[100, 255]), EpdColor::DarkGray);
+        assert_eq!(quantize(&[0, 0, 0, 0]), EpdColor::White);
+        assert_eq!(quantize(&[150, 150, 150]), EpdColor::Gray);
+    }
+}
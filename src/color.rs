@@ -1,5 +1,8 @@
 //! B/W/G/DG Color for EPDs
 
+/// Number of pixels stored in one byte of a packed buffer
+pub const PIXELS_PER_BYTE: usize = 4;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum EpdColor {
@@ -44,6 +47,21 @@ impl EpdColor {
         }
     }
 
+    /// Reads the pixel at `index` from a buffer that packs 4 pixels into each byte
+    ///
+    /// The first pixel is stored in the most significant bits
+    pub fn from_packed(buffer: &[u8], index: usize) -> EpdColor {
+        let shift = packed_shift(index);
+        EpdColor::from_u8((buffer[index / PIXELS_PER_BYTE] >> shift) & 0b11)
+    }
+
+    /// Writes the color of the pixel at `index` into a buffer that packs 4 pixels into each byte
+    pub fn write_packed(self, buffer: &mut [u8], index: usize) {
+        let shift = packed_shift(index);
+        let byte = &mut buffer[index / PIXELS_PER_BYTE];
+        *byte = (*byte & !(0b11 << shift)) | (self.get_bit_value() << shift);
+    }
+
     /// Returns the inverse of the given color.
     ///
     /// Black returns White and White returns Black
@@ -57,6 +75,31 @@ impl EpdColor {
     }
 }
 
+/// Counts the pixels of every color, indexed by [get_bit_value()](EpdColor::get_bit_value())
+pub fn histogram<I: IntoIterator<Item = EpdColor>>(pixels: I) -> [usize; 4] {
+    let mut counts = [0; 4];
+    for color in pixels {
+        counts[color.get_bit_value() as usize] += 1;
+    }
+    counts
+}
+
+/// Returns the most frequent color of a histogram, ties are won by `preferred`
+pub fn most_frequent(counts: &[usize; 4], preferred: EpdColor) -> EpdColor {
+    let mut best = preferred;
+    for (value, &count) in counts.iter().enumerate() {
+        if count > counts[best.get_bit_value() as usize] {
+            best = EpdColor::from_u8(value as u8);
+        }
+    }
+    best
+}
+
+// bit position of the pixel at `index` inside its byte
+fn packed_shift(index: usize) -> u8 {
+    6 - 2 * (index % PIXELS_PER_BYTE) as u8
+}
+
 #[cfg(feature = "graphics")]
 use embedded_graphics::prelude::PixelColor;
 #[cfg(feature = "graphics")]
@@ -92,6 +135,33 @@ mod tests {
         }
     }
 
+    #[test]
+    fn packed_conversion() {
+        let mut buffer = [EpdColor::White.get_byte_value(); 2];
+        EpdColor::Black.write_packed(&mut buffer, 0);
+        EpdColor::Gray.write_packed(&mut buffer, 3);
+        EpdColor::DarkGray.write_packed(&mut buffer, 5);
+        assert_eq!(buffer, [0b0011_1110, 0b1101_1111]);
+
+        assert_eq!(EpdColor::from_packed(&buffer, 0), EpdColor::Black);
+        assert_eq!(EpdColor::from_packed(&buffer, 1), EpdColor::White);
+        assert_eq!(EpdColor::from_packed(&buffer, 3), EpdColor::Gray);
+        assert_eq!(EpdColor::from_packed(&buffer, 5), EpdColor::DarkGray);
+    }
+
+    #[test]
+    fn histogram_works() {
+        use EpdColor::*;
+        let counts = histogram([Black, White, Gray, Black].iter().copied());
+        assert_eq!(counts, [2, 0, 1, 1]);
+        assert_eq!(most_frequent(&counts, White), Black);
+
+        let counts = histogram([Black, White].iter().copied());
+        assert_eq!(most_frequent(&counts, White), White);
+        assert_eq!(most_frequent(&counts, Black), Black);
+        assert_eq!(most_frequent(&counts, Gray), Black);
+    }
+
     #[test]
     fn u8_conversion_black() {
         assert_eq!(
@@ -0,0 +1,548 @@
+//! Async driver on top of [embedded_io_async] and [embedded_hal_async]
+//!
+//! Sends the same frames as [EPD4in3](crate::epd4in3::EPD4in3), but waits for the device without
+//! blocking the executor. Every answer is awaited with a timeout, a frame which isn't
+//! acknowledged in time is sent again.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let mut epd4in3 = EPD4in3Async::new(uart, Some(wake), Some(rst), Delay);
+//! epd4in3.init().await?;
+//!
+//! epd4in3.update_frame(&display.buffer()).await?;
+//! epd4in3.display_frame().await?;
+//! ```
+
+use core::cmp;
+use embassy_futures::select::{select, Either};
+use embedded_hal_1::digital::OutputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_io_async::{Read, ReadExactError, Write};
+
+use crate::color::EpdColor;
+use crate::epd4in3::protocol::{PixelOps, ReplyMatcher};
+use crate::epd4in3::{
+    Op, DEFAULT_BACKGROUND_COLOR, DEFAULT_FOREGROUND_COLOR, DEFAULT_RETRIES, HEIGHT, WIDTH,
+};
+use crate::interface::{DEFAULT_PULSE_WIDTH_MS, DEFAULT_READY_TIMEOUT_MS};
+use crate::traits::Error;
+
+/// Default for how long the device may take to answer a frame
+pub const DEFAULT_ACK_TIMEOUT_MS: u32 = 1000;
+/// Pause between two handshakes while waiting for the device
+const POLL_INTERVAL_MS: u32 = 20;
+
+/// Errors of the async driver
+///
+/// Reading fails with a [ReadExactError], so the end of the input is reported as
+/// [ReadExactError::UnexpectedEof]. Writing fails with the error of the serial port.
+pub type AsyncError<S, G, H> = Error<ReadExactError<S>, S, G, H>;
+
+/// Async EPD4in3 driver which owns the serial port and the delay
+pub struct EPD4in3Async<SERIAL, WAKE, RST, DELAY> {
+    serial: SERIAL,
+    wake: Option<WAKE>,
+    rst: Option<RST>,
+    delay: DELAY,
+    bg_color: EpdColor,
+    fg_color: EpdColor,
+    pulse_width_ms: u16,
+    ready_timeout_ms: u32,
+    ack_timeout_ms: u32,
+    retries: u8,
+}
+
+/// Async EPD4in3 driver while the display is in deep sleep, see [EPD4in3Async::sleep()]
+pub struct EPD4in3AsyncSleeping<SERIAL, WAKE, RST, DELAY> {
+    epd: EPD4in3Async<SERIAL, WAKE, RST, DELAY>,
+}
+
+impl<SERIAL, WAKE, RST, DELAY> EPD4in3Async<SERIAL, WAKE, RST, DELAY> {
+    /// Creates a new driver, nothing is sent before [init()](EPD4in3Async::init())
+    pub fn new(serial: SERIAL, wake: Option<WAKE>, rst: Option<RST>, delay: DELAY) -> Self {
+        EPD4in3Async {
+            serial,
+            wake,
+            rst,
+            delay,
+            bg_color: DEFAULT_BACKGROUND_COLOR,
+            fg_color: DEFAULT_FOREGROUND_COLOR,
+            pulse_width_ms: DEFAULT_PULSE_WIDTH_MS,
+            ready_timeout_ms: DEFAULT_READY_TIMEOUT_MS,
+            ack_timeout_ms: DEFAULT_ACK_TIMEOUT_MS,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    /// Destroys the driver and returns the serial port, the pins and the delay
+    pub fn release(self) -> (SERIAL, Option<WAKE>, Option<RST>, DELAY) {
+        (self.serial, self.wake, self.rst, self.delay)
+    }
+
+    /// Sets how long the WAKE and RST pins are held low and high when pulsed, 10 ms by default
+    pub fn set_pulse_width(&mut self, ms: u16) {
+        self.pulse_width_ms = ms;
+    }
+
+    /// Sets how long resetting and waking up wait for the device to answer a handshake,
+    /// 5 s by default
+    pub fn set_ready_timeout(&mut self, ms: u32) {
+        self.ready_timeout_ms = ms;
+    }
+
+    /// Sets how long the device may take to answer a frame, 1 s by default
+    pub fn set_ack_timeout(&mut self, ms: u32) {
+        self.ack_timeout_ms = ms;
+    }
+
+    /// Sets how often a frame is sent again when the device doesn't acknowledge it, 9 by default
+    pub fn set_retries(&mut self, retries: u8) {
+        self.retries = retries;
+    }
+
+    pub fn set_background_color(&mut self, color: EpdColor) {
+        self.bg_color = color;
+    }
+
+    pub fn set_foreground_color(&mut self, color: EpdColor) {
+        self.fg_color = color;
+    }
+
+    pub fn width(&self) -> u32 {
+        WIDTH
+    }
+
+    pub fn height(&self) -> u32 {
+        HEIGHT
+    }
+}
+
+impl<S, G, H, SERIAL, WAKE, RST, DELAY> EPD4in3Async<SERIAL, WAKE, RST, DELAY>
+where
+    SERIAL: Read<Error = S> + Write<Error = S>,
+    WAKE: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
+    DELAY: DelayNs,
+{
+    /// Resets the device and waits until it answers a handshake
+    pub async fn init(&mut self) -> Result<(), AsyncError<S, G, H>> {
+        if let Some(rst) = self.rst.as_mut() {
+            pulse(rst, &mut self.delay, self.pulse_width_ms)
+                .await
+                .map_err(Error::GpioRst)?;
+        }
+        self.wait_ready().await
+    }
+
+    /// Wakes the device up and waits until it answers a handshake
+    pub async fn wake_up(&mut self) -> Result<(), AsyncError<S, G, H>> {
+        if let Some(wake) = self.wake.as_mut() {
+            pulse(wake, &mut self.delay, self.pulse_width_ms)
+                .await
+                .map_err(Error::GpioE)?;
+        }
+        self.wait_ready().await
+    }
+
+    /// Sends a handshake and checks that the device answers it with `OK` in time
+    pub async fn handshake(&mut self) -> Result<bool, AsyncError<S, G, H>> {
+        self.write_op(&Op::Handshake).await?;
+        Ok(self.read_reply(self.ack_timeout_ms).await? == Some(true))
+    }
+
+    // Sends handshakes until the device answers one or the ready timeout passed
+    //
+    // Waiting for the answers counts towards the ready timeout as well as the pauses in between
+    async fn wait_ready(&mut self) -> Result<(), AsyncError<S, G, H>> {
+        let mut waited_ms = 0;
+        loop {
+            self.write_op(&Op::Handshake).await?;
+            let reply_ms = cmp::min(
+                self.ack_timeout_ms,
+                self.ready_timeout_ms.saturating_sub(waited_ms),
+            );
+            match self.read_reply(reply_ms).await? {
+                Some(true) => return Ok(()),
+                Some(false) => {}
+                None => waited_ms += reply_ms,
+            }
+            if waited_ms >= self.ready_timeout_ms {
+                return Err(Error::Timeout);
+            }
+            self.delay.delay_ms(POLL_INTERVAL_MS).await;
+            waited_ms += POLL_INTERVAL_MS;
+        }
+    }
+
+    /// Sends a single command and waits until the device acknowledges it
+    ///
+    /// Returns `Error::Timeout` if it wasn't acknowledged after all retries and `Error::Sleep` for
+    /// [Op::Sleep], which is sent by [sleep()](EPD4in3Async::sleep()).
+    pub async fn send(&mut self, op: &Op<'_>) -> Result<(), AsyncError<S, G, H>> {
+        if *op == Op::Sleep {
+            return Err(Error::Sleep);
+        }
+        self.send_acked(op).await
+    }
+
+    /// Sends a list of commands, see [EPD4in3::draw_ops()](crate::epd4in3::EPD4in3::draw_ops())
+    pub async fn draw_ops(&mut self, ops: &[Op<'_>]) -> Result<(), AsyncError<S, G, H>> {
+        if ops.contains(&Op::Sleep) {
+            return Err(Error::Sleep);
+        }
+        for op in ops.iter() {
+            self.send_acked(op).await?;
+        }
+        Ok(())
+    }
+
+    // Sends a frame until it is acknowledged
+    //
+    // If an answer didn't arrive in time, all input is discarded until the line stays quiet for
+    // the ack timeout before the frame is sent again, so a late answer can't acknowledge it.
+    async fn send_acked(&mut self, op: &Op<'_>) -> Result<(), AsyncError<S, G, H>> {
+        for _ in 0..=self.retries {
+            self.write_op(op).await?;
+            match self.read_reply(self.ack_timeout_ms).await? {
+                Some(true) => {
+                    // the colors only change once the device knows about them
+                    if let Op::SetColor(fg_color, bg_color) = *op {
+                        self.fg_color = fg_color;
+                        self.bg_color = bg_color;
+                    }
+                    return Ok(());
+                }
+                Some(false) => {}
+                None => self.discard_input().await?,
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Transmits all pixels which differ from the background color
+    ///
+    /// Sends the same frames as [update_frame()](crate::prelude::WaveshareDisplay::update_frame())
+    /// of the blocking driver.
+    pub async fn update_frame(&mut self, buffer: &[EpdColor]) -> Result<(), AsyncError<S, G, H>> {
+        let ops = PixelOps::new(buffer.iter().cloned(), self.fg_color, self.bg_color);
+        for op in ops {
+            self.send_acked(&op).await?;
+        }
+        Ok(())
+    }
+
+    /// Displays the frame data from SRAM
+    pub async fn display_frame(&mut self) -> Result<(), AsyncError<S, G, H>> {
+        self.send(&Op::Refresh).await
+    }
+
+    /// Clears the frame buffer on the EPD with the background color
+    pub async fn clear_frame(&mut self) -> Result<(), AsyncError<S, G, H>> {
+        self.send(&Op::Clear).await
+    }
+
+    /// Lets the device enter deep-sleep mode, see [EPD4in3::sleep()](crate::epd4in3::EPD4in3::sleep())
+    ///
+    /// If the device doesn't acknowledge it, the driver is returned along with the error.
+    pub async fn sleep(
+        mut self,
+    ) -> Result<EPD4in3AsyncSleeping<SERIAL, WAKE, RST, DELAY>, (Self, AsyncError<S, G, H>)> {
+        match self.send_acked(&Op::Sleep).await {
+            Ok(()) => Ok(EPD4in3AsyncSleeping { epd: self }),
+            Err(e) => Err((self, e)),
+        }
+    }
+
+    async fn write_op(&mut self, op: &Op<'_>) -> Result<(), AsyncError<S, G, H>> {
+        let frame = op.frame().map_err(|_| Error::Frame)?;
+        self.serial
+            .write_all(frame.get_bytes())
+            .await
+            .map_err(Error::SerialW)?;
+        self.serial.flush().await.map_err(Error::SerialW)
+    }
+
+    // Drops everything received until nothing arrived for the ack timeout
+    async fn discard_input(&mut self) -> Result<(), AsyncError<S, G, H>> {
+        let mut buffer = [0; 16];
+        loop {
+            let read = self.serial.read(&mut buffer);
+            match select(read, self.delay.delay_ms(self.ack_timeout_ms)).await {
+                Either::First(Ok(0)) | Either::Second(()) => return Ok(()),
+                Either::First(Ok(_)) => {}
+                Either::First(Err(e)) => return Err(Error::SerialR(ReadExactError::Other(e))),
+            }
+        }
+    }
+
+    // Waits for `OK` or `Error`, returns `None` if neither arrived within `timeout_ms`
+    async fn read_reply(&mut self, timeout_ms: u32) -> Result<Option<bool>, AsyncError<S, G, H>> {
+        let serial = &mut self.serial;
+        let read = async {
+            let mut matcher = ReplyMatcher::default();
+            let mut byte = [0];
+            loop {
+                match serial.read(&mut byte).await {
+                    Ok(0) => return Err(ReadExactError::UnexpectedEof),
+                    Ok(_) => {}
+                    Err(e) => return Err(ReadExactError::Other(e)),
+                }
+                if let Some(ok) = matcher.push(byte[0]) {
+                    return Ok(ok);
+                }
+            }
+        };
+        match select(read, self.delay.delay_ms(timeout_ms)).await {
+            Either::First(reply) => reply.map(Some).map_err(Error::SerialR),
+            Either::Second(()) => Ok(None),
+        }
+    }
+}
+
+impl<SERIAL, WAKE, RST, DELAY> EPD4in3AsyncSleeping<SERIAL, WAKE, RST, DELAY> {
+    /// Destroys the driver and returns the serial port, the pins and the delay
+    pub fn release(self) -> (SERIAL, Option<WAKE>, Option<RST>, DELAY) {
+        self.epd.release()
+    }
+}
+
+impl<S, G, H, SERIAL, WAKE, RST, DELAY> EPD4in3AsyncSleeping<SERIAL, WAKE, RST, DELAY>
+where
+    SERIAL: Read<Error = S> + Write<Error = S>,
+    WAKE: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
+    DELAY: DelayNs,
+{
+    /// Wakes the display up and returns the driver
+    ///
+    /// If the display doesn't wake up, the sleeping driver is returned along with the error.
+    pub async fn wake_up(
+        self,
+    ) -> Result<EPD4in3Async<SERIAL, WAKE, RST, DELAY>, (Self, AsyncError<S, G, H>)> {
+        let mut epd = self.epd;
+        match epd.wake_up().await {
+            Ok(()) => Ok(epd),
+            Err(e) => Err((EPD4in3AsyncSleeping { epd }, e)),
+        }
+    }
+}
+
+// Drives a pin low, high and low again, keeping each level for `width_ms`
+async fn pulse<P: OutputPin, DELAY: DelayNs>(
+    pin: &mut P,
+    delay: &mut DELAY,
+    width_ms: u16,
+) -> Result<(), P::Error> {
+    pin.set_low()?;
+    delay.delay_ms(u32::from(width_ms)).await;
+    pin.set_high()?;
+    delay.delay_ms(u32::from(width_ms)).await;
+    pin.set_low()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epd4in3::command;
+    use embassy_futures::block_on;
+    use embedded_hal_mock::eh1::delay::NoopDelay;
+    use embedded_hal_mock::eh1::digital::{Mock as PinMock, State, Transaction};
+    extern crate std;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    // serial port answering reads from a list, reads wait forever once it is empty
+    //
+    // `None` in the list lets a read wait until it is given up, like an answer arriving late.
+    // With `eof` set, the end of the input is reached once the list is empty.
+    #[derive(Default)]
+    struct MockSerial {
+        written: Vec<u8>,
+        replies: VecDeque<Option<u8>>,
+        eof: bool,
+    }
+
+    impl embedded_io_async::ErrorType for MockSerial {
+        type Error = embedded_io_async::ErrorKind;
+    }
+
+    impl Read for MockSerial {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            match self.replies.pop_front() {
+                Some(Some(byte)) => {
+                    buf[0] = byte;
+                    Ok(1)
+                }
+                None if self.eof => Ok(0),
+                Some(None) | None => core::future::pending().await,
+            }
+        }
+    }
+
+    impl Write for MockSerial {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+    }
+
+    type Driver = EPD4in3Async<MockSerial, PinMock, PinMock, NoopDelay>;
+
+    fn driver(replies: &[u8]) -> Driver {
+        let mut serial = MockSerial::default();
+        serial
+            .replies
+            .extend(replies.iter().map(|&byte| Some(byte)));
+        EPD4in3Async::new(serial, None, None, NoopDelay::new())
+    }
+
+    fn frames(ops: &[Op]) -> Vec<u8> {
+        ops.iter()
+            .flat_map(|op| op.frame().unwrap().get_bytes().to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn sends_frames() {
+        let pulse = [
+            Transaction::set(State::Low),
+            Transaction::set(State::High),
+            Transaction::set(State::Low),
+        ];
+        let mut rst = PinMock::new(&pulse);
+        let mut serial = MockSerial::default();
+        serial.replies.extend(b"OK".repeat(5).into_iter().map(Some));
+        let mut epd =
+            EPD4in3Async::new(serial, None::<PinMock>, Some(rst.clone()), NoopDelay::new());
+
+        let mut buffer = [EpdColor::White; 8];
+        buffer[1] = EpdColor::Gray;
+        buffer[2] = EpdColor::Gray;
+        block_on(async {
+            epd.init().await?;
+            epd.update_frame(&buffer).await?;
+            epd.display_frame().await
+        })
+        .unwrap();
+
+        let (serial, _, _, _) = epd.release();
+        assert_eq!(
+            serial.written,
+            frames(&[
+                Op::Handshake,
+                Op::SetColor(EpdColor::Gray, EpdColor::White),
+                Op::Point(1, 0),
+                Op::Point(2, 0),
+                Op::Refresh,
+            ])
+        );
+        rst.done();
+    }
+
+    #[test]
+    fn retries_until_acknowledged() {
+        // rejected, then an answer split into pieces
+        let mut epd = driver(b"ErrorO\0OK");
+        block_on(epd.send(&Op::Clear)).unwrap();
+        let clear = command::clear().unwrap();
+        assert_eq!(epd.serial.written, [clear.get_bytes(); 2].concat());
+        assert!(epd.serial.replies.is_empty());
+
+        // no answer at all
+        let mut epd = driver(b"");
+        epd.set_retries(2);
+        match block_on(epd.send(&Op::Clear)) {
+            Err(Error::Timeout) => {}
+            _ => panic!("expected a timeout"),
+        }
+        assert_eq!(epd.serial.written, [clear.get_bytes(); 3].concat());
+    }
+
+    #[test]
+    fn reports_end_of_input() {
+        let mut epd = driver(b"");
+        epd.serial.eof = true;
+        match block_on(epd.send(&Op::Clear)) {
+            Err(Error::SerialR(ReadExactError::UnexpectedEof)) => {}
+            _ => panic!("expected the end of the input"),
+        }
+        assert_eq!(epd.serial.written, frames(&[Op::Clear]));
+    }
+
+    #[test]
+    fn keeps_colors_of_failed_frames() {
+        let mut epd = driver(b"ErrorOKOK");
+        epd.set_retries(0);
+        let gray = Op::SetColor(EpdColor::Gray, EpdColor::White);
+        assert!(block_on(epd.draw_ops(&[gray, Op::Point(1, 1)])).is_err());
+
+        // the device never switched to gray, so the next frame switches again
+        block_on(epd.update_frame(&[EpdColor::White, EpdColor::Gray])).unwrap();
+        assert_eq!(epd.serial.written, frames(&[gray, gray, Op::Point(1, 0)]));
+    }
+
+    #[test]
+    fn ignores_late_answers() {
+        let mut epd = driver(b"");
+        // answered after the ack timeout, must not acknowledge the frame sent again
+        epd.serial.replies.push_back(None);
+        epd.serial
+            .replies
+            .extend(b"OK".iter().map(|&byte| Some(byte)));
+        epd.serial.replies.push_back(None);
+        epd.serial
+            .replies
+            .extend(b"ErrorOKOK".iter().map(|&byte| Some(byte)));
+
+        block_on(epd.draw_ops(&[Op::Clear, Op::Refresh])).unwrap();
+        assert_eq!(
+            epd.serial.written,
+            frames(&[Op::Clear, Op::Clear, Op::Clear, Op::Refresh])
+        );
+    }
+
+    #[test]
+    fn keeps_driver_if_sleep_fails() {
+        let mut epd = driver(b"");
+        epd.set_retries(0);
+        let epd = match block_on(epd.sleep()) {
+            Err((epd, Error::Timeout)) => epd,
+            _ => panic!("expected a timeout"),
+        };
+
+        let (serial, _, _, _) = epd.release();
+        assert_eq!(serial.written, frames(&[Op::Sleep]));
+    }
+
+    #[test]
+    fn sleeps_and_wakes_up() {
+        let mut epd = driver(b"OKOK");
+        for result in [
+            block_on(epd.send(&Op::Sleep)),
+            block_on(epd.draw_ops(&[Op::Refresh, Op::Sleep])),
+        ] {
+            match result {
+                Err(Error::Sleep) => {}
+                _ => panic!("expected the sleep command to be rejected"),
+            }
+        }
+        assert!(epd.serial.written.is_empty());
+
+        let sleeping = block_on(epd.sleep()).ok().unwrap();
+        let mut epd = block_on(sleeping.wake_up()).ok().unwrap();
+        assert_eq!(epd.serial.written, frames(&[Op::Sleep, Op::Handshake]));
+
+        // not ready within the timeout, which includes waiting for the answers
+        epd.set_ready_timeout(40);
+        epd.set_ack_timeout(10);
+        match block_on(epd.wake_up()) {
+            Err(Error::Timeout) => {}
+            _ => panic!("expected a timeout"),
+        }
+        assert_eq!(
+            epd.serial.written,
+            frames(&[Op::Sleep, Op::Handshake, Op::Handshake, Op::Handshake])
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Configuring the driver before connecting to the display
+
+use embedded_hal::{
+    blocking::{delay::DelayMs, serial::Write},
+    digital::v2::OutputPin,
+    serial::Read,
+};
+
+use crate::adapter::NoPin;
+use crate::color::EpdColor;
+use crate::epd4in3::command::{self, Fontsize, Rotation};
+use crate::epd4in3::{
+    DriverResult, EPD4in3, DEFAULT_BACKGROUND_COLOR, DEFAULT_FOREGROUND_COLOR, DEFAULT_RETRIES,
+};
+use crate::interface::{DisplayInterface, DEFAULT_PULSE_WIDTH_MS, DEFAULT_READY_TIMEOUT_MS};
+
+/// How the driver checks that the device received a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckMode {
+    /// Waits for the device to answer every frame and sends it again if it doesn't answer `OK`
+    Wait,
+    /// Sends every frame once without reading the answers, the serial port isn't read at all
+    Ignore,
+}
+
+/// Builder for an [EPD4in3] driver
+///
+/// Everything not set keeps the defaults of [EPD4in3::new()](crate::prelude::WaveshareDisplay::new()).
+/// The colors, the rotation and the font sizes are sent to the device by [build()](EPD4in3Builder::build()).
+///
+/// # Example
+///
+/// ```ignore
+/// let mut epd4in3 = EPD4in3Builder::new()
+///     .rst(rst)
+///     .pulse_width(5)
+///     .rotation(Rotation::Rotation180)
+///     .colors(EpdColor::White, EpdColor::Black)
+///     .build(&mut serial, &mut delay)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct EPD4in3Builder<WAKE, RST> {
+    wake: Option<WAKE>,
+    rst: Option<RST>,
+    pulse_width_ms: u16,
+    ready_timeout_ms: u32,
+    ack_mode: AckMode,
+    retries: u8,
+    colors: Option<(EpdColor, EpdColor)>,
+    rotation: Option<Rotation>,
+    font_size_en: Option<Fontsize>,
+    font_size_zh: Option<Fontsize>,
+    reset: bool,
+}
+
+impl EPD4in3Builder<NoPin, NoPin> {
+    /// Starts without WAKE and RST pins
+    pub fn new() -> Self {
+        EPD4in3Builder {
+            wake: None,
+            rst: None,
+            pulse_width_ms: DEFAULT_PULSE_WIDTH_MS,
+            ready_timeout_ms: DEFAULT_READY_TIMEOUT_MS,
+            ack_mode: AckMode::Wait,
+            retries: DEFAULT_RETRIES,
+            colors: None,
+            rotation: None,
+            font_size_en: None,
+            font_size_zh: None,
+            reset: true,
+        }
+    }
+}
+
+impl Default for EPD4in3Builder<NoPin, NoPin> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<WAKE, RST> EPD4in3Builder<WAKE, RST> {
+    /// Uses a WAKE pin for waking the device up
+    pub fn wake<W>(self, wake: W) -> EPD4in3Builder<W, RST> {
+        EPD4in3Builder {
+            wake: Some(wake),
+            rst: self.rst,
+            pulse_width_ms: self.pulse_width_ms,
+            ready_timeout_ms: self.ready_timeout_ms,
+            ack_mode: self.ack_mode,
+            retries: self.retries,
+            colors: self.colors,
+            rotation: self.rotation,
+            font_size_en: self.font_size_en,
+            font_size_zh: self.font_size_zh,
+            reset: self.reset,
+        }
+    }
+
+    /// Uses a RST pin for resetting the device
+    pub fn rst<R>(self, rst: R) -> EPD4in3Builder<WAKE, R> {
+        EPD4in3Builder {
+            wake: self.wake,
+            rst: Some(rst),
+            pulse_width_ms: self.pulse_width_ms,
+            ready_timeout_ms: self.ready_timeout_ms,
+            ack_mode: self.ack_mode,
+            retries: self.retries,
+            colors: self.colors,
+            rotation: self.rotation,
+            font_size_en: self.font_size_en,
+            font_size_zh: self.font_size_zh,
+            reset: self.reset,
+        }
+    }
+
+    /// How long the WAKE and RST pins are held low and high when pulsed, 10 ms by default
+    pub fn pulse_width(mut self, ms: u16) -> Self {
+        self.pulse_width_ms = ms;
+        self
+    }
+
+    /// How long resetting and waking up wait for the device to answer a handshake, 5 s by default
+    pub fn ready_timeout(mut self, ms: u32) -> Self {
+        self.ready_timeout_ms = ms;
+        self
+    }
+
+    /// How frames are acknowledged, [AckMode::Wait] by default
+    pub fn ack_mode(mut self, ack_mode: AckMode) -> Self {
+        self.ack_mode = ack_mode;
+        self
+    }
+
+    /// How often a frame is sent again when the device doesn't acknowledge it, 9 by default
+    ///
+    /// Sending fails with `Error::Timeout` once all retries were rejected.
+    pub fn retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Foreground and background color, black on white by default
+    pub fn colors(mut self, foreground: EpdColor, background: EpdColor) -> Self {
+        self.colors = Some((foreground, background));
+        self
+    }
+
+    /// Rotation of the display, left as it is by default
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = Some(rotation);
+        self
+    }
+
+    /// Size of english text, left as it is by default
+    pub fn font_size_en(mut self, fontsize: Fontsize) -> Self {
+        self.font_size_en = Some(fontsize);
+        self
+    }
+
+    /// Size of chinese text, left as it is by default
+    pub fn font_size_zh(mut self, fontsize: Fontsize) -> Self {
+        self.font_size_zh = Some(fontsize);
+        self
+    }
+
+    /// Whether the device is reset while building, otherwise it only has to answer a handshake
+    ///
+    /// Resets by default, without a RST pin there is only a handshake either way.
+    pub fn reset(mut self, reset: bool) -> Self {
+        self.reset = reset;
+        self
+    }
+
+    /// Connects to the device and sends the configuration to it
+    pub fn build<E, F, G, H, SERIAL, DELAY>(
+        self,
+        serial: &mut SERIAL,
+        delay: &mut DELAY,
+    ) -> DriverResult<EPD4in3<SERIAL, WAKE, RST>, E, F, G, H>
+    where
+        SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
+        WAKE: OutputPin<Error = G>,
+        RST: OutputPin<Error = H>,
+        DELAY: DelayMs<u16>,
+    {
+        let mut interface = DisplayInterface::new(self.wake, self.rst);
+        interface.set_pulse_width(self.pulse_width_ms);
+        interface.set_ready_timeout(self.ready_timeout_ms);
+        if self.reset {
+            interface.reset(serial, delay)?;
+        } else {
+            interface.wait_ready(serial, delay)?;
+        }
+
+        let mut epd = EPD4in3 {
+            interface,
+            bg_color: DEFAULT_BACKGROUND_COLOR,
+            fg_color: DEFAULT_FOREGROUND_COLOR,
+            ack_mode: self.ack_mode,
+            retries: self.retries,
+        };
+        if let Some((fg_color, bg_color)) = self.colors {
+            epd.fg_color = fg_color;
+            epd.bg_color = bg_color;
+            epd.send_acked(serial, None, |s| {
+                command::write_set_color(s, fg_color, bg_color)
+            })?;
+        }
+        if let Some(rotation) = self.rotation {
+            epd.send_acked(serial, None, |s| command::write_set_rotation(s, rotation))?;
+        }
+        if let Some(fontsize) = self.font_size_en {
+            epd.send_acked(serial, None, |s| {
+                command::write_set_font_size_en(s, fontsize)
+            })?;
+        }
+        if let Some(fontsize) = self.font_size_zh {
+            epd.send_acked(serial, None, |s| {
+                command::write_set_font_size_zh(s, fontsize)
+            })?;
+        }
+        Ok(epd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epd4in3::Op;
+    use crate::mock::{MockDelay, MockPin, MockSerial};
+    use crate::traits::Error;
+
+    #[test]
+    fn build_sends_configuration() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let epd = EPD4in3Builder::new()
+            .rst(MockPin::default())
+            .pulse_width(1)
+            .colors(EpdColor::White, EpdColor::Black)
+            .rotation(Rotation::Rotation180)
+            .font_size_zh(Fontsize::Size64)
+            .build(&mut serial, &mut delay)
+            .unwrap();
+
+        let mut expected = command::handshake().unwrap().get_bytes().to_vec();
+        for frame in [
+            command::set_color(EpdColor::White, EpdColor::Black),
+            command::set_rotation(Rotation::Rotation180),
+            command::set_font_size_zh(Fontsize::Size64),
+        ]
+        .iter()
+        {
+            expected.extend_from_slice(frame.as_ref().unwrap().get_bytes());
+        }
+        assert_eq!(serial.written, expected);
+        assert_eq!(delay.total_ms, 2);
+        assert_eq!(
+            (epd.fg_color, epd.bg_color),
+            (EpdColor::White, EpdColor::Black)
+        );
+    }
+
+    #[test]
+    fn ack_mode_and_retries_work() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = EPD4in3Builder::new()
+            .wake(MockPin::default())
+            .reset(false)
+            .retries(1)
+            .build(&mut serial, &mut delay)
+            .unwrap();
+        assert_eq!(delay.total_ms, 0);
+
+        // sent twice, then given up
+        serial = MockSerial::default();
+        serial.replies.extend(b"\0\0\0\0".iter());
+        match epd.draw_ops(&mut serial, &[Op::Refresh]) {
+            Err(Error::Timeout) => {}
+            _ => panic!("expected a timeout"),
+        }
+        let refresh = command::refresh().unwrap();
+        assert_eq!(serial.written, [refresh.get_bytes(); 2].concat());
+
+        let mut serial = MockSerial::default();
+        let mut epd = EPD4in3Builder::new()
+            .ack_mode(AckMode::Ignore)
+            .build(&mut serial, &mut delay)
+            .unwrap();
+        serial = MockSerial::default();
+        let ops = [Op::Point(1, 2), Op::Refresh];
+        epd.draw_ops(&mut serial, &ops).unwrap();
+        assert_eq!(serial.read, 0);
+    }
+}
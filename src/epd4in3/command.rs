@@ -1,11 +1,23 @@
 use crate::color::EpdColor;
 use crate::traits;
 use arrayvec::ArrayVec;
+use embedded_hal::blocking::serial::Write;
 use encoding_rs::*;
 
+/// Maximum size of a frame in bytes
+pub const MAX_FRAME_LEN: usize = 1033;
+/// Maximum size of the payload of a frame in bytes
+pub const MAX_PAYLOAD_LEN: usize = MAX_FRAME_LEN - 9;
+
+const FRAME_HEADER: u8 = 0xA5;
+const FRAME_END: [u8; 4] = [0xcc, 0x33, 0xc3, 0x3c];
+
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Command {
     Handshake = 0,
+    SetBaud = 0x01,
+    SetStorage = 0x07,
     LoadFont = 0x0e,
     LoadBmp = 0x0f,
     Clear = 0x2e,
@@ -28,9 +40,43 @@ pub enum Command {
 }
 
 impl Command {
+    /// All commands
+    pub const ALL: [Command; 22] = [
+        Command::Handshake,
+        Command::SetBaud,
+        Command::SetStorage,
+        Command::LoadFont,
+        Command::LoadBmp,
+        Command::Clear,
+        Command::Update,
+        Command::Sleep,
+        Command::SetRotation,
+        Command::SetColor,
+        Command::SetFontSizeEn,
+        Command::SetFontSizeZh,
+        Command::Point,
+        Command::Line,
+        Command::Rect,
+        Command::FillRect,
+        Command::Circle,
+        Command::FillCircle,
+        Command::Tri,
+        Command::FillTri,
+        Command::Text,
+        Command::Bmp,
+    ];
+
     fn value(self) -> u8 {
         self as u8
     }
+
+    /// Parses the command from its address, `None` for unknown addresses
+    pub fn from_address(address: u8) -> Option<Command> {
+        Command::ALL
+            .iter()
+            .copied()
+            .find(|cmd| cmd.value() == address)
+    }
 }
 
 impl traits::Command for Command {
@@ -41,22 +87,195 @@ impl traits::Command for Command {
 }
 
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Rotation {
     Rotation0 = 0,
     Rotation180 = 1,
 }
 
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Fontsize {
     Size32 = 1,
     Size48 = 2,
     Size64 = 3,
 }
 
+/// Where the module loads fonts and bitmaps from
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Storage {
+    Nand = 0,
+    MicroSd = 1,
+}
+
+/// Destination for encoded frames
+///
+/// Implemented for every blocking serial [Write] and for [SliceSink], so frames can be
+/// written without building them in memory first
+pub trait FrameSink {
+    type Error;
+
+    /// Writes all bytes to the sink
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error>;
+}
+
+impl<S: Write<u8>> FrameSink for S {
+    type Error = S::Error;
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.bwrite_all(bytes)
+    }
+}
+
+/// The sink ran out of space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overflow;
+
+/// Writes frames into a caller-provided slice
+pub struct SliceSink<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl<'a> SliceSink<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        SliceSink { buf, pos: 0 }
+    }
+
+    /// Returns the bytes written so far
+    pub fn written(&self) -> &[u8] {
+        &self.buf[0..self.pos]
+    }
+}
+
+impl<'a> FrameSink for SliceSink<'a> {
+    type Error = Overflow;
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        let end = self.pos + bytes.len();
+        if end > self.buf.len() {
+            return Err(Overflow);
+        }
+        self.buf[self.pos..end].copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+}
+
+// Only counts the bytes, used to find the length of a payload before sending it
+struct CountingSink(usize);
+
+impl FrameSink for CountingSink {
+    type Error = Overflow;
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0 += bytes.len();
+        if self.0 > MAX_PAYLOAD_LEN {
+            return Err(Overflow);
+        }
+        Ok(())
+    }
+}
+
+/// Errors while encoding a frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameError<E> {
+    /// The sink failed to take the bytes
+    Sink(E),
+    /// The payload doesn't fit into a frame or doesn't match the announced length
+    Length,
+    /// The text can't be encoded
+    Text(TextError),
+    /// The filename isn't a valid bmp name
+    Filename,
+}
+
+impl<E, F, G, H> From<FrameError<F>> for traits::Error<E, F, G, H> {
+    fn from(error: FrameError<F>) -> Self {
+        match error {
+            FrameError::Sink(e) => traits::Error::SerialW(e),
+            _ => traits::Error::Frame,
+        }
+    }
+}
+
+/// Writes a single frame piece by piece to a [FrameSink]
+///
+/// The parity is computed while writing, so no part of the frame is kept in memory
+pub struct FrameWriter<'a, S: FrameSink> {
+    sink: &'a mut S,
+    remaining: usize,
+    parity: u8,
+}
+
+impl<'a, S: FrameSink> FrameWriter<'a, S> {
+    /// Writes the header of a frame for `cmd` with a payload of `payload_len` bytes
+    pub fn begin(
+        sink: &'a mut S,
+        cmd: Command,
+        payload_len: usize,
+    ) -> Result<Self, FrameError<S::Error>> {
+        if payload_len > MAX_PAYLOAD_LEN {
+            return Err(FrameError::Length);
+        }
+        let [len_high, len_low] = ((payload_len + 9) as u16).to_be_bytes();
+        let mut writer = FrameWriter {
+            sink,
+            remaining: payload_len,
+            parity: 0x00,
+        };
+        writer.write_raw(&[FRAME_HEADER, len_high, len_low, cmd.value()])?;
+        Ok(writer)
+    }
+
+    /// Writes a part of the payload
+    pub fn push(&mut self, bytes: &[u8]) -> Result<(), FrameError<S::Error>> {
+        if bytes.len() > self.remaining {
+            return Err(FrameError::Length);
+        }
+        self.remaining -= bytes.len();
+        self.write_raw(bytes)
+    }
+
+    /// Writes a big endian u16 of the payload
+    pub fn push_u16(&mut self, value: u16) -> Result<(), FrameError<S::Error>> {
+        self.push(&value.to_be_bytes())
+    }
+
+    /// Writes the frame end and the parity byte
+    pub fn finish(mut self) -> Result<(), FrameError<S::Error>> {
+        if self.remaining != 0 {
+            return Err(FrameError::Length);
+        }
+        self.write_raw(&FRAME_END)?;
+        let parity = self.parity;
+        self.sink.write(&[parity]).map_err(FrameError::Sink)
+    }
+
+    fn write_raw(&mut self, bytes: &[u8]) -> Result<(), FrameError<S::Error>> {
+        for byte in bytes.iter() {
+            self.parity ^= *byte;
+        }
+        self.sink.write(bytes).map_err(FrameError::Sink)
+    }
+}
+
+impl<'a, S: FrameSink> FrameSink for FrameWriter<'a, S> {
+    type Error = FrameError<S::Error>;
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.push(bytes)
+    }
+}
+
+/// A complete frame kept in memory
+///
+/// Convenience layer on top of the `write_*` functions, which write directly to a [FrameSink]
 #[allow(dead_code)]
 pub struct Frame {
     len: u16,
-    bytes: [u8; 1033],
+    bytes: [u8; MAX_FRAME_LEN],
 }
 
 impl Frame {
@@ -65,218 +284,449 @@ impl Frame {
     }
 }
 
-fn push_u16_to_array(value: u16, array: &mut ArrayVec<[u8; 1024]>) {
-    for byte in value.to_be_bytes().iter() {
-        array.push(*byte);
+impl FrameSink for Frame {
+    type Error = Overflow;
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Self::Error> {
+        let start = self.len as usize;
+        let end = start + bytes.len();
+        if end > MAX_FRAME_LEN {
+            return Err(Overflow);
+        }
+        self.bytes[start..end].copy_from_slice(bytes);
+        self.len = end as u16;
+        Ok(())
     }
 }
 
-fn build_frame(cmd: Command, args: &ArrayVec<[u8; 1024]>) -> Option<Frame> {
-    let len: u16 = 9 + args.len() as u16;
-    if len > 1033 {
-        return None;
+pub(crate) fn build_frame<W>(write: W) -> Result<Frame, FrameError<Overflow>>
+where
+    W: FnOnce(&mut Frame) -> Result<(), FrameError<Overflow>>,
+{
+    let mut frame = Frame {
+        len: 0,
+        bytes: [0; MAX_FRAME_LEN],
     };
+    write(&mut frame)?;
+    Ok(frame)
+}
+
+fn write_empty<S: FrameSink>(sink: &mut S, cmd: Command) -> Result<(), FrameError<S::Error>> {
+    FrameWriter::begin(sink, cmd, 0)?.finish()
+}
 
-    let mut bytes = [0; 1033];
-    let mut pos: usize = 1;
-    bytes[0] = 0xA5;
+fn write_u8<S: FrameSink>(
+    sink: &mut S,
+    cmd: Command,
+    value: u8,
+) -> Result<(), FrameError<S::Error>> {
+    let mut writer = FrameWriter::begin(sink, cmd, 1)?;
+    writer.push(&[value])?;
+    writer.finish()
+}
 
-    for byte in len.to_be_bytes().iter() {
-        bytes[pos] = *byte;
-        pos += 1;
+fn write_u16s<S: FrameSink>(
+    sink: &mut S,
+    cmd: Command,
+    values: &[u16],
+) -> Result<(), FrameError<S::Error>> {
+    let mut writer = FrameWriter::begin(sink, cmd, values.len() * 2)?;
+    for &value in values.iter() {
+        writer.push_u16(value)?;
     }
+    writer.finish()
+}
+
+pub fn write_handshake<S: FrameSink>(sink: &mut S) -> Result<(), FrameError<S::Error>> {
+    write_empty(sink, Command::Handshake)
+}
+
+/// Changes the baud rate of the module, it answers with the old baud rate
+pub fn write_set_baud<S: FrameSink>(sink: &mut S, baud: u32) -> Result<(), FrameError<S::Error>> {
+    let mut writer = FrameWriter::begin(sink, Command::SetBaud, 4)?;
+    writer.push(&baud.to_be_bytes())?;
+    writer.finish()
+}
+
+pub fn write_set_storage<S: FrameSink>(
+    sink: &mut S,
+    storage: Storage,
+) -> Result<(), FrameError<S::Error>> {
+    write_u8(sink, Command::SetStorage, storage as u8)
+}
+
+pub fn write_load_font<S: FrameSink>(sink: &mut S) -> Result<(), FrameError<S::Error>> {
+    write_empty(sink, Command::LoadFont)
+}
+
+pub fn write_load_bmp<S: FrameSink>(sink: &mut S) -> Result<(), FrameError<S::Error>> {
+    write_empty(sink, Command::LoadBmp)
+}
 
-    bytes[pos] = cmd.value();
-    pos += 1;
+pub fn write_clear<S: FrameSink>(sink: &mut S) -> Result<(), FrameError<S::Error>> {
+    write_empty(sink, Command::Clear)
+}
+
+pub fn write_refresh<S: FrameSink>(sink: &mut S) -> Result<(), FrameError<S::Error>> {
+    write_empty(sink, Command::Update)
+}
+
+pub fn write_sleep<S: FrameSink>(sink: &mut S) -> Result<(), FrameError<S::Error>> {
+    write_empty(sink, Command::Sleep)
+}
+
+pub fn write_set_rotation<S: FrameSink>(
+    sink: &mut S,
+    rot: Rotation,
+) -> Result<(), FrameError<S::Error>> {
+    write_u8(sink, Command::SetRotation, rot as u8)
+}
+
+pub fn write_point<S: FrameSink>(
+    sink: &mut S,
+    x0: u16,
+    y0: u16,
+) -> Result<(), FrameError<S::Error>> {
+    write_u16s(sink, Command::Point, &[x0, y0])
+}
+
+pub fn write_line<S: FrameSink>(
+    sink: &mut S,
+    x0: u16,
+    y0: u16,
+    x1: u16,
+    y1: u16,
+) -> Result<(), FrameError<S::Error>> {
+    write_u16s(sink, Command::Line, &[x0, y0, x1, y1])
+}
+
+pub fn write_rect<S: FrameSink>(
+    sink: &mut S,
+    x0: u16,
+    y0: u16,
+    x1: u16,
+    y1: u16,
+) -> Result<(), FrameError<S::Error>> {
+    write_u16s(sink, Command::Rect, &[x0, y0, x1, y1])
+}
+
+pub fn write_fill_rect<S: FrameSink>(
+    sink: &mut S,
+    x0: u16,
+    y0: u16,
+    x1: u16,
+    y1: u16,
+) -> Result<(), FrameError<S::Error>> {
+    write_u16s(sink, Command::FillRect, &[x0, y0, x1, y1])
+}
+
+pub fn write_circle<S: FrameSink>(
+    sink: &mut S,
+    x0: u16,
+    y0: u16,
+    r: u16,
+) -> Result<(), FrameError<S::Error>> {
+    write_u16s(sink, Command::Circle, &[x0, y0, r])
+}
+
+pub fn write_fill_circle<S: FrameSink>(
+    sink: &mut S,
+    x0: u16,
+    y0: u16,
+    r: u16,
+) -> Result<(), FrameError<S::Error>> {
+    write_u16s(sink, Command::FillCircle, &[x0, y0, r])
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn write_tri<S: FrameSink>(
+    sink: &mut S,
+    x0: u16,
+    y0: u16,
+    x1: u16,
+    y1: u16,
+    x2: u16,
+    y2: u16,
+) -> Result<(), FrameError<S::Error>> {
+    write_u16s(sink, Command::Tri, &[x0, y0, x1, y1, x2, y2])
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn write_fill_tri<S: FrameSink>(
+    sink: &mut S,
+    x0: u16,
+    y0: u16,
+    x1: u16,
+    y1: u16,
+    x2: u16,
+    y2: u16,
+) -> Result<(), FrameError<S::Error>> {
+    write_u16s(sink, Command::FillTri, &[x0, y0, x1, y1, x2, y2])
+}
+
+/// Replacement used by [text()](text()) for characters GBK can't encode
+pub const DEFAULT_REPLACEMENT: char = '?';
+
+/// How [text_with()](text_with()) handles characters that GBK can't encode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unmappable {
+    /// Refuse to build the frame and report the offending characters
+    Strict,
+    /// Substitute every offending character with the given one
+    Replace(char),
+}
 
-    for byte in args.iter() {
-        bytes[pos] = *byte;
-        pos += 1;
+/// Errors while building a text frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextError {
+    /// The encoded text doesn't fit into a single frame
+    TooLong,
+    /// The text contains characters GBK can't encode
+    ///
+    /// Lists the first distinct offending characters in order of appearance
+    Unmappable(ArrayVec<[char; 8]>),
+    /// The replacement character can't be encoded in GBK either
+    InvalidReplacement(char),
+}
+
+// Writes the GBK encoding of `txt` to `sink`
+fn write_gbk<S: FrameSink>(
+    txt: &str,
+    mode: Unmappable,
+    sink: &mut S,
+) -> Result<(), FrameError<S::Error>> {
+    let mut encoder = GBK.new_encoder();
+    let mut unmappable = ArrayVec::<[char; 8]>::new();
+    let mut src = txt;
+
+    loop {
+        let mut chunk = [0u8; 16];
+        let (result, read, written) =
+            encoder.encode_from_utf8_without_replacement(src, &mut chunk, true);
+        sink.write(&chunk[0..written]).map_err(FrameError::Sink)?;
+        src = &src[read..];
+
+        match result {
+            EncoderResult::InputEmpty => break,
+            EncoderResult::OutputFull => {}
+            EncoderResult::Unmappable(c) => match mode {
+                Unmappable::Strict => {
+                    if !unmappable.contains(&c) {
+                        let _ = unmappable.try_push(c);
+                    }
+                }
+                Unmappable::Replace(replacement) => {
+                    let mut utf8 = [0u8; 4];
+                    let (result, _, written) =
+                        GBK.new_encoder().encode_from_utf8_without_replacement(
+                            replacement.encode_utf8(&mut utf8),
+                            &mut chunk,
+                            true,
+                        );
+                    if result != EncoderResult::InputEmpty {
+                        return Err(FrameError::Text(TextError::InvalidReplacement(replacement)));
+                    }
+                    sink.write(&chunk[0..written]).map_err(FrameError::Sink)?;
+                }
+            },
+        }
+    }
+
+    if unmappable.is_empty() {
+        Ok(())
+    } else {
+        Err(FrameError::Text(TextError::Unmappable(unmappable)))
     }
+}
 
-    let frame_end: [u8; 4] = [0xcc, 0x33, 0xc3, 0x3c];
-    for byte in frame_end.iter() {
-        bytes[pos] = *byte;
-        pos += 1;
+/// Writes a text frame
+///
+/// The text is encoded twice, once to find its length and once while writing it
+pub fn write_text<S: FrameSink>(
+    sink: &mut S,
+    x0: u16,
+    y0: u16,
+    txt: &str,
+    mode: Unmappable,
+) -> Result<(), FrameError<S::Error>> {
+    let mut counter = CountingSink(0);
+    match write_gbk(txt, mode, &mut counter) {
+        Ok(()) => {}
+        Err(FrameError::Text(e)) => return Err(FrameError::Text(e)),
+        Err(_) => return Err(FrameError::Text(TextError::TooLong)),
+    }
+    // coordinates and the terminating zero
+    let payload_len = counter.0 + 5;
+    if payload_len > MAX_PAYLOAD_LEN {
+        return Err(FrameError::Text(TextError::TooLong));
     }
 
-    let mut parity: u8 = 0x00;
-    for byte in bytes[0..pos].iter() {
-        parity ^= *byte;
+    let mut writer = FrameWriter::begin(sink, Command::Text, payload_len)?;
+    writer.push_u16(x0)?;
+    writer.push_u16(y0)?;
+    match write_gbk(txt, mode, &mut writer) {
+        Ok(()) => {}
+        Err(FrameError::Sink(e)) => return Err(e),
+        Err(_) => return Err(FrameError::Length),
     }
+    writer.push(&[0x00])?;
+    writer.finish()
+}
+
+pub fn write_bmp<S: FrameSink>(
+    sink: &mut S,
+    x0: u16,
+    y0: u16,
+    txt: &str,
+) -> Result<(), FrameError<S::Error>> {
+    if txt.len() > 11 {
+        return Err(FrameError::Filename);
+    };
+    if !txt.is_ascii() {
+        return Err(FrameError::Filename);
+    };
 
-    bytes[pos] = parity;
+    let mut writer = FrameWriter::begin(sink, Command::Bmp, txt.len() + 5)?;
+    writer.push_u16(x0)?;
+    writer.push_u16(y0)?;
+    writer.push(txt.as_bytes())?;
+    writer.push(&[0x00])?;
+    writer.finish()
+}
+
+pub fn write_set_font_size_en<S: FrameSink>(
+    sink: &mut S,
+    fontsize: Fontsize,
+) -> Result<(), FrameError<S::Error>> {
+    write_u8(sink, Command::SetFontSizeEn, fontsize as u8)
+}
 
-    Some(Frame { len, bytes })
+pub fn write_set_font_size_zh<S: FrameSink>(
+    sink: &mut S,
+    fontsize: Fontsize,
+) -> Result<(), FrameError<S::Error>> {
+    write_u8(sink, Command::SetFontSizeZh, fontsize as u8)
+}
+
+pub fn write_set_color<S: FrameSink>(
+    sink: &mut S,
+    foreground: EpdColor,
+    background: EpdColor,
+) -> Result<(), FrameError<S::Error>> {
+    let mut writer = FrameWriter::begin(sink, Command::SetColor, 2)?;
+    writer.push(&[foreground as u8, background as u8])?;
+    writer.finish()
 }
 
 pub fn handshake() -> Option<Frame> {
-    build_frame(Command::Handshake, &ArrayVec::<[_; 1024]>::new())
+    build_frame(write_handshake).ok()
+}
+
+pub fn set_baud(baud: u32) -> Option<Frame> {
+    build_frame(|f| write_set_baud(f, baud)).ok()
+}
+
+pub fn set_storage(storage: Storage) -> Option<Frame> {
+    build_frame(|f| write_set_storage(f, storage)).ok()
 }
 
 pub fn load_font() -> Option<Frame> {
-    build_frame(Command::LoadFont, &ArrayVec::<[_; 1024]>::new())
+    build_frame(write_load_font).ok()
 }
 
 pub fn load_bmp() -> Option<Frame> {
-    build_frame(Command::LoadBmp, &ArrayVec::<[_; 1024]>::new())
+    build_frame(write_load_bmp).ok()
 }
 
 pub fn clear() -> Option<Frame> {
-    build_frame(Command::Clear, &ArrayVec::<[_; 1024]>::new())
+    build_frame(write_clear).ok()
 }
 
 pub fn refresh() -> Option<Frame> {
-    build_frame(Command::Update, &ArrayVec::<[_; 1024]>::new())
+    build_frame(write_refresh).ok()
 }
 
 pub fn sleep() -> Option<Frame> {
-    build_frame(Command::Sleep, &ArrayVec::<[_; 1024]>::new())
+    build_frame(write_sleep).ok()
 }
 
 pub fn set_rotation(rot: Rotation) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    array.push(rot as u8);
-    build_frame(Command::SetRotation, &array)
+    build_frame(|f| write_set_rotation(f, rot)).ok()
 }
 
 pub fn point(x0: u16, y0: u16) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    push_u16_to_array(x0, &mut array);
-    push_u16_to_array(y0, &mut array);
-
-    build_frame(Command::Point, &array)
+    build_frame(|f| write_point(f, x0, y0)).ok()
 }
 
 pub fn line(x0: u16, y0: u16, x1: u16, y1: u16) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    push_u16_to_array(x0, &mut array);
-    push_u16_to_array(y0, &mut array);
-    push_u16_to_array(x1, &mut array);
-    push_u16_to_array(y1, &mut array);
-
-    build_frame(Command::Line, &array)
+    build_frame(|f| write_line(f, x0, y0, x1, y1)).ok()
 }
 
 pub fn rect(x0: u16, y0: u16, x1: u16, y1: u16) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    push_u16_to_array(x0, &mut array);
-    push_u16_to_array(y0, &mut array);
-    push_u16_to_array(x1, &mut array);
-    push_u16_to_array(y1, &mut array);
-
-    build_frame(Command::Rect, &array)
+    build_frame(|f| write_rect(f, x0, y0, x1, y1)).ok()
 }
 
 pub fn fill_rect(x0: u16, y0: u16, x1: u16, y1: u16) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    push_u16_to_array(x0, &mut array);
-    push_u16_to_array(y0, &mut array);
-    push_u16_to_array(x1, &mut array);
-    push_u16_to_array(y1, &mut array);
-
-    build_frame(Command::FillRect, &array)
+    build_frame(|f| write_fill_rect(f, x0, y0, x1, y1)).ok()
 }
 
 pub fn circle(x0: u16, y0: u16, r: u16) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    push_u16_to_array(x0, &mut array);
-    push_u16_to_array(y0, &mut array);
-    push_u16_to_array(r, &mut array);
-
-    build_frame(Command::Circle, &array)
+    build_frame(|f| write_circle(f, x0, y0, r)).ok()
 }
 
 pub fn fill_circle(x0: u16, y0: u16, r: u16) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    push_u16_to_array(x0, &mut array);
-    push_u16_to_array(y0, &mut array);
-    push_u16_to_array(r, &mut array);
-
-    build_frame(Command::FillCircle, &array)
+    build_frame(|f| write_fill_circle(f, x0, y0, r)).ok()
 }
 
 pub fn tri(x0: u16, y0: u16, x1: u16, y1: u16, x2: u16, y2: u16) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    push_u16_to_array(x0, &mut array);
-    push_u16_to_array(y0, &mut array);
-    push_u16_to_array(x1, &mut array);
-    push_u16_to_array(y1, &mut array);
-    push_u16_to_array(x2, &mut array);
-    push_u16_to_array(y2, &mut array);
-
-    build_frame(Command::Tri, &array)
+    build_frame(|f| write_tri(f, x0, y0, x1, y1, x2, y2)).ok()
 }
 
 pub fn fill_tri(x0: u16, y0: u16, x1: u16, y1: u16, x2: u16, y2: u16) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    push_u16_to_array(x0, &mut array);
-    push_u16_to_array(y0, &mut array);
-    push_u16_to_array(x1, &mut array);
-    push_u16_to_array(y1, &mut array);
-    push_u16_to_array(x2, &mut array);
-    push_u16_to_array(y2, &mut array);
-
-    build_frame(Command::FillTri, &array)
+    build_frame(|f| write_fill_tri(f, x0, y0, x1, y1, x2, y2)).ok()
 }
 
+/// Text frame, characters GBK can't encode are replaced with [DEFAULT_REPLACEMENT]
 pub fn text(x0: u16, y0: u16, txt: &str) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    push_u16_to_array(x0, &mut array);
-    push_u16_to_array(y0, &mut array);
-
-    let res = GBK.encode(txt);
-
-    for byte in res.0.iter() {
-        array.push(*byte);
-    }
-    array.push(0x00);
+    text_with(x0, y0, txt, Unmappable::Replace(DEFAULT_REPLACEMENT)).ok()
+}
 
-    build_frame(Command::Text, &array)
+/// Text frame with a choice of how to handle characters GBK can't encode
+pub fn text_with(x0: u16, y0: u16, txt: &str, mode: Unmappable) -> Result<Frame, TextError> {
+    build_frame(|f| write_text(f, x0, y0, txt, mode)).map_err(|e| match e {
+        FrameError::Text(e) => e,
+        _ => TextError::TooLong,
+    })
 }
 
 pub fn bmp(x0: u16, y0: u16, txt: &str) -> Option<Frame> {
-    if txt.len() > 11 {
-        return None;
-    };
-    if !txt.is_ascii() {
-        return None;
-    };
-
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    push_u16_to_array(x0, &mut array);
-    push_u16_to_array(y0, &mut array);
-
-    for byte in txt.as_bytes().iter() {
-        array.push(*byte);
-    }
-    array.push(0x00);
-
-    build_frame(Command::Bmp, &array)
+    build_frame(|f| write_bmp(f, x0, y0, txt)).ok()
 }
 
 pub fn set_font_size_en(fontsize: Fontsize) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    array.push(fontsize as u8);
-    build_frame(Command::SetFontSizeEn, &array)
+    build_frame(|f| write_set_font_size_en(f, fontsize)).ok()
 }
 
 pub fn set_font_size_zh(fontsize: Fontsize) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    array.push(fontsize as u8);
-    build_frame(Command::SetFontSizeZh, &array)
+    build_frame(|f| write_set_font_size_zh(f, fontsize)).ok()
 }
 
 pub fn set_color(foreground: EpdColor, background: EpdColor) -> Option<Frame> {
-    let mut array = ArrayVec::<[_; 1024]>::new();
-    array.push(foreground as u8);
-    array.push(background as u8);
-    build_frame(Command::SetColor, &array)
+    build_frame(|f| write_set_color(f, foreground, background)).ok()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn from_address_works() {
+        for &cmd in Command::ALL.iter() {
+            assert_eq!(Command::from_address(cmd as u8), Some(cmd));
+        }
+        assert_eq!(Command::from_address(0xff), None);
+    }
+
     #[test]
     fn handshake_works() {
         let frame = handshake().unwrap();
@@ -322,6 +772,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn set_baud_works() {
+        let frame = set_baud(115_200).unwrap();
+        assert_eq!(
+            frame.bytes[0..(frame.len as usize)],
+            [0xA5, 0x00, 0x0D, 0x01, 0x00, 0x01, 0xC2, 0x00, 0xCC, 0x33, 0xC3, 0x3C, 0x6A]
+        );
+    }
+
+    #[test]
+    fn set_storage_works() {
+        let frame = set_storage(Storage::MicroSd).unwrap();
+        assert_eq!(
+            frame.bytes[0..(frame.len as usize)],
+            [0xA5, 0x00, 0x0A, 0x07, 0x01, 0xCC, 0x33, 0xC3, 0x3C, 0xA9]
+        );
+    }
+
     #[test]
     fn sleep_works() {
         let frame = sleep().unwrap();
@@ -446,6 +914,93 @@ mod tests {
         );
     }
 
+    #[test]
+    fn text_replaces_unmappable() {
+        let frame = text(0x0a, 0x0a, "A😀").unwrap();
+        assert_eq!(
+            frame.bytes[0..(frame.len as usize)],
+            [
+                0xA5, 0x00, 0x10, 0x30, 0x00, 0x0a, 0x00, 0x0a, 0x41, 0x3f, 0x00, 0xCC, 0x33, 0xC3,
+                0x3C, 0xfb
+            ]
+        );
+    }
+
+    #[test]
+    fn text_with_replacement_works() {
+        let replaced = text_with(0x0a, 0x0a, "A😀", Unmappable::Replace('好')).unwrap();
+        let direct = text_with(0x0a, 0x0a, "A好", Unmappable::Strict).unwrap();
+        assert_eq!(replaced.get_bytes(), direct.get_bytes());
+    }
+
+    #[test]
+    fn text_with_invalid_replacement() {
+        let res = text_with(0x0a, 0x0a, "A😀", Unmappable::Replace('🦀'));
+        assert_eq!(res.err(), Some(TextError::InvalidReplacement('🦀')));
+    }
+
+    #[test]
+    fn text_strict_lists_unmappable() {
+        let res = text_with(0x0a, 0x0a, "😀你🦀好😀", Unmappable::Strict);
+        let mut expected = ArrayVec::new();
+        expected.push('😀');
+        expected.push('🦀');
+        assert_eq!(res.err(), Some(TextError::Unmappable(expected)));
+    }
+
+    #[test]
+    fn text_too_long() {
+        let txt = core::str::from_utf8(&[b'a'; 1024]).unwrap();
+        assert!(text(0x0a, 0x0a, txt).is_none());
+        assert_eq!(
+            text_with(0x0a, 0x0a, txt, Unmappable::Strict).err(),
+            Some(TextError::TooLong)
+        );
+
+        // fits into a frame only without the coordinates
+        let mut buf = [0u8; MAX_FRAME_LEN];
+        let mut sink = SliceSink::new(&mut buf);
+        assert_eq!(
+            write_text(&mut sink, 0x0a, 0x0a, txt, Unmappable::Strict),
+            Err(FrameError::Text(TextError::TooLong))
+        );
+    }
+
+    #[test]
+    fn write_to_slice_matches_frame() {
+        let mut buf = [0u8; 64];
+        let mut sink = SliceSink::new(&mut buf);
+        write_point(&mut sink, 0xa, 0xa).unwrap();
+        write_text(&mut sink, 0x0a, 0x0a, "你好World", Unmappable::Strict).unwrap();
+
+        let point = point(0xa, 0xa).unwrap();
+        let text = text(0x0a, 0x0a, "你好World").unwrap();
+        let (first, second) = sink.written().split_at(point.get_bytes().len());
+        assert_eq!(first, point.get_bytes());
+        assert_eq!(second, text.get_bytes());
+    }
+
+    #[test]
+    fn write_to_small_slice_fails() {
+        let mut buf = [0u8; 8];
+        let mut sink = SliceSink::new(&mut buf);
+        assert_eq!(write_sleep(&mut sink), Err(FrameError::Sink(Overflow)));
+    }
+
+    #[test]
+    fn frame_writer_checks_length() {
+        let mut buf = [0u8; 64];
+        let mut sink = SliceSink::new(&mut buf);
+        let mut writer = FrameWriter::begin(&mut sink, Command::Point, 4).unwrap();
+        writer.push_u16(0x0a).unwrap();
+        assert_eq!(writer.finish(), Err(FrameError::Length));
+
+        let mut writer = FrameWriter::begin(&mut sink, Command::Point, 1).unwrap();
+        assert_eq!(writer.push_u16(0x0a), Err(FrameError::Length));
+
+        assert!(FrameWriter::begin(&mut sink, Command::Text, MAX_PAYLOAD_LEN + 1).is_err());
+    }
+
     #[test]
     fn bmp_works() {
         let txt = "PIC7.BMP";
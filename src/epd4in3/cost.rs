@@ -0,0 +1,155 @@
+//! Estimating the cost of an upload without a display
+
+use core::convert::Infallible;
+use embedded_hal::{blocking::serial::Write, serial::Read};
+
+use crate::epd4in3::command::Command;
+
+/// What an upload costs, collected by a [CostCounter]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UploadCost {
+    frames: [u32; Command::ALL.len()],
+    /// Number of bytes sent to the display
+    pub bytes_written: u32,
+    /// Number of bytes read from the display
+    pub bytes_read: u32,
+}
+
+impl UploadCost {
+    /// Number of frames sent with the given command
+    pub fn frames(&self, cmd: Command) -> u32 {
+        self.frames[slot(cmd)]
+    }
+
+    /// Number of frames sent in total
+    pub fn total_frames(&self) -> u32 {
+        self.frames.iter().sum()
+    }
+
+    /// Number of `OK` replies the display is expected to send
+    pub fn acks(&self) -> u32 {
+        self.bytes_read / 2
+    }
+
+    /// Estimated time in ms to transfer everything at `baud` with 8N1, `None` for a baud rate of 0
+    ///
+    /// Only the time on the wire is counted, not the time the display needs to process a frame
+    pub fn duration_ms(&self, baud: u32) -> Option<u64> {
+        let bits = u64::from(self.bytes_written + self.bytes_read) * 10;
+        (bits * 1000).checked_div(u64::from(baud))
+    }
+}
+
+// Position of a command in `UploadCost::frames`
+fn slot(cmd: Command) -> usize {
+    Command::ALL
+        .iter()
+        .position(|&c| c == cmd)
+        .expect("Command::ALL lists every command")
+}
+
+/// Serial port which only counts what is sent and acknowledges every frame with `OK`
+///
+/// See [EPD4in3::dry_run()](crate::epd4in3::EPD4in3::dry_run())
+#[derive(Debug, Clone, Default)]
+pub struct CostCounter {
+    cost: UploadCost,
+    // bytes of the current frame not yet seen, 0 while waiting for a new frame
+    remaining: u16,
+    header: [u8; 4],
+    header_len: usize,
+}
+
+impl CostCounter {
+    /// Creates a counter with nothing counted yet
+    pub fn new() -> Self {
+        CostCounter::default()
+    }
+
+    /// Returns the cost counted so far
+    pub fn cost(&self) -> &UploadCost {
+        &self.cost
+    }
+
+    fn count(&mut self, byte: u8) {
+        self.cost.bytes_written += 1;
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            return;
+        }
+
+        // collect header, length and command of the next frame
+        self.header[self.header_len] = byte;
+        self.header_len += 1;
+        if self.header[0] != 0xA5 {
+            self.header_len = 0;
+        } else if self.header_len == self.header.len() {
+            self.header_len = 0;
+            let len = u16::from_be_bytes([self.header[1], self.header[2]]);
+            self.remaining = len.saturating_sub(4);
+            if let Some(cmd) = Command::from_address(self.header[3]) {
+                self.cost.frames[slot(cmd)] += 1;
+            }
+        }
+    }
+}
+
+impl Write<u8> for CostCounter {
+    type Error = Infallible;
+
+    fn bwrite_all(&mut self, buffer: &[u8]) -> Result<(), Self::Error> {
+        for &byte in buffer.iter() {
+            self.count(byte);
+        }
+        Ok(())
+    }
+
+    fn bflush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl Read<u8> for CostCounter {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let byte = b"OK"[self.cost.bytes_read as usize & 1];
+        self.cost.bytes_read += 1;
+        Ok(byte)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epd4in3::command;
+
+    #[test]
+    fn counts_frames() {
+        let mut counter = CostCounter::new();
+        command::write_handshake(&mut counter).unwrap();
+        command::write_line(&mut counter, 0, 0, 10, 10).unwrap();
+        command::write_line(&mut counter, 0, 10, 10, 0).unwrap();
+        command::write_text(&mut counter, 0, 0, "abc", command::Unmappable::Strict).unwrap();
+
+        let cost = counter.cost();
+        assert_eq!(cost.frames(Command::Handshake), 1);
+        assert_eq!(cost.frames(Command::Line), 2);
+        assert_eq!(cost.frames(Command::Text), 1);
+        assert_eq!(cost.frames(Command::Point), 0);
+        assert_eq!(cost.bytes_written, 9 + 2 * 17 + 9 + 4 + 4);
+        assert_eq!(cost.acks(), 0);
+    }
+
+    #[test]
+    fn duration_works() {
+        let cost = UploadCost {
+            bytes_written: 1150,
+            bytes_read: 2,
+            ..UploadCost::default()
+        };
+        assert_eq!(cost.duration_ms(115_200), Some(100));
+        assert_eq!(cost.duration_ms(9600), Some(1200));
+        assert_eq!(cost.duration_ms(0), None);
+    }
+}
@@ -0,0 +1,209 @@
+//! Recorded lists of commands
+
+use arrayvec::{Array, ArrayVec};
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+use crate::epd4in3::command::{FrameError, FrameSink, Overflow};
+use crate::epd4in3::op::Op;
+use crate::epd4in3::optimize::optimize;
+
+/// Storage of the commands of a [DisplayList]
+///
+/// Implemented for [ArrayVec] with a fixed capacity and for `Vec` with the `std` feature
+pub trait OpStorage<'a> {
+    /// Appends a command, fails if there is no space left
+    fn push(&mut self, op: Op<'a>) -> Result<(), Overflow>;
+
+    /// Shortens the storage to `len` commands
+    fn truncate(&mut self, len: usize);
+
+    fn as_slice(&self) -> &[Op<'a>];
+
+    fn as_mut_slice(&mut self) -> &mut [Op<'a>];
+}
+
+impl<'a, A: Array<Item = Op<'a>>> OpStorage<'a> for ArrayVec<A> {
+    fn push(&mut self, op: Op<'a>) -> Result<(), Overflow> {
+        self.try_push(op).map_err(|_| Overflow)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        ArrayVec::truncate(self, len)
+    }
+
+    fn as_slice(&self) -> &[Op<'a>] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [Op<'a>] {
+        self
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a> OpStorage<'a> for Vec<Op<'a>> {
+    fn push(&mut self, op: Op<'a>) -> Result<(), Overflow> {
+        Vec::push(self, op);
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len)
+    }
+
+    fn as_slice(&self) -> &[Op<'a>] {
+        self
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [Op<'a>] {
+        self
+    }
+}
+
+/// A list of commands, built once and sent as often as needed
+///
+/// # Example
+///
+/// ```ignore
+/// let mut screen: DisplayList<ArrayVec<[Op; 16]>> = DisplayList::new();
+/// screen.push(Op::Clear)?;
+/// screen.push(Op::Text(10, 10, "Status"))?;
+/// screen.push(Op::Refresh)?;
+///
+/// epd4in3.draw_ops(&mut serial, screen.ops())?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DisplayList<S> {
+    storage: S,
+}
+
+impl<'a, S: OpStorage<'a>> DisplayList<S> {
+    /// Creates an empty list
+    pub fn new() -> Self
+    where
+        S: Default,
+    {
+        DisplayList {
+            storage: S::default(),
+        }
+    }
+
+    /// Creates a list recording into `storage`
+    pub fn with_storage(storage: S) -> Self {
+        DisplayList { storage }
+    }
+
+    /// Appends a command, fails if the storage is full
+    pub fn push(&mut self, op: Op<'a>) -> Result<(), Overflow> {
+        self.storage.push(op)
+    }
+
+    /// The recorded commands
+    pub fn ops(&self) -> &[Op<'a>] {
+        self.storage.as_slice()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops().is_empty()
+    }
+
+    /// Removes all commands
+    pub fn clear(&mut self) {
+        self.storage.truncate(0)
+    }
+
+    /// Removes redundant commands without changing what is drawn, see [optimize()]
+    pub fn optimize(&mut self) {
+        let len = optimize(self.storage.as_mut_slice());
+        self.storage.truncate(len)
+    }
+
+    /// Writes the frames of all commands to `sink` without waiting for any acknowledgement
+    pub fn write<W: FrameSink>(&self, sink: &mut W) -> Result<(), FrameError<W::Error>> {
+        for op in self.ops().iter() {
+            op.write(sink)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the storage with the recorded commands
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+}
+
+impl<'a, S: OpStorage<'a>> Extend<Op<'a>> for DisplayList<S> {
+    /// Appends the commands, panics if the storage gets full
+    fn extend<I: IntoIterator<Item = Op<'a>>>(&mut self, ops: I) {
+        for op in ops {
+            self.push(op).expect("display list is full");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::EpdColor;
+    use crate::epd4in3::command::{self, SliceSink};
+
+    type List<'a> = DisplayList<ArrayVec<[Op<'a>; 4]>>;
+
+    #[test]
+    fn records_and_writes() {
+        let mut list = List::new();
+        list.push(Op::SetColor(EpdColor::Gray, EpdColor::White))
+            .unwrap();
+        list.push(Op::Text(1, 2, "Hi")).unwrap();
+        list.push(Op::Refresh).unwrap();
+        assert_eq!(list.len(), 3);
+
+        let mut buffer = [0; 64];
+        let mut sink = SliceSink::new(&mut buffer);
+        list.write(&mut sink).unwrap();
+
+        let mut expected = [0; 64];
+        let mut expected_sink = SliceSink::new(&mut expected);
+        for frame in [
+            command::set_color(EpdColor::Gray, EpdColor::White).unwrap(),
+            command::text(1, 2, "Hi").unwrap(),
+            command::refresh().unwrap(),
+        ]
+        .iter()
+        {
+            FrameSink::write(&mut expected_sink, frame.get_bytes()).unwrap();
+        }
+        assert_eq!(sink.written(), expected_sink.written());
+    }
+
+    #[test]
+    fn full_list() {
+        let mut list = List::new();
+        list.extend(
+            [Op::Point(0, 0), Op::Point(1, 0), Op::Point(2, 0)]
+                .iter()
+                .copied(),
+        );
+        list.push(Op::Refresh).unwrap();
+        assert_eq!(list.push(Op::Clear), Err(Overflow));
+
+        list.optimize();
+        assert_eq!(list.ops(), &[Op::Line(0, 0, 2, 0), Op::Refresh]);
+        list.clear();
+        assert!(list.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn vec_storage() {
+        let mut list: DisplayList<Vec<Op>> = DisplayList::new();
+        list.extend((0..100).map(|x| Op::Point(x, 0)));
+        list.optimize();
+        assert_eq!(list.into_storage(), [Op::Line(0, 0, 99, 0)]);
+    }
+}
@@ -1,15 +1,19 @@
 use crate::epd4in3::{DEFAULT_BACKGROUND_COLOR, HEIGHT, WIDTH};
-use crate::graphics::{Display, DisplayRotation};
+use crate::graphics::{DirtyRegions, DirtyTracking, Display, DisplayRotation};
 use crate::prelude::*;
 use embedded_graphics::prelude::*;
 
 /// Full size buffer for use with the 4in3 EPD
 ///
 /// Can also be manuall constructed:
-/// `buffer: [DEFAULT_BACKGROUND_COLOR.get_byte_value(); WIDTH / 8 * HEIGHT]`
+/// `buffer: [DEFAULT_BACKGROUND_COLOR; WIDTH * HEIGHT]`
+///
+/// Needs 480000 bytes, see [PackedDisplay](crate::graphics::PackedDisplay) for a display
+/// needing only a quarter of that
 pub struct Display4in3 {
     buffer: [EpdColor; WIDTH as usize * HEIGHT as usize],
     rotation: DisplayRotation,
+    dirty: DirtyRegions,
 }
 
 impl Default for Display4in3 {
@@ -17,6 +21,7 @@ impl Default for Display4in3 {
         Display4in3 {
             buffer: [DEFAULT_BACKGROUND_COLOR; WIDTH as usize * HEIGHT as usize],
             rotation: DisplayRotation::default(),
+            dirty: DirtyRegions::new(WIDTH, HEIGHT),
         }
     }
 }
@@ -31,6 +36,8 @@ impl Drawing<EpdColor> for Display4in3 {
 }
 
 impl Display for Display4in3 {
+    type Buffer = [EpdColor];
+
     fn buffer(&self) -> &[EpdColor] {
         &self.buffer
     }
@@ -39,6 +46,10 @@ impl Display for Display4in3 {
         &mut self.buffer
     }
 
+    fn dirty_tracker(&mut self) -> Option<&mut DirtyRegions> {
+        Some(&mut self.dirty)
+    }
+
     fn set_rotation(&mut self, rotation: DisplayRotation) {
         self.rotation = rotation;
     }
@@ -48,6 +59,20 @@ impl Display for Display4in3 {
     }
 }
 
+impl DirtyTracking for Display4in3 {
+    fn dirty(&self) -> &DirtyRegions {
+        &self.dirty
+    }
+
+    fn get_mut_dirty(&mut self) -> &mut DirtyRegions {
+        &mut self.dirty
+    }
+
+    fn width(&self) -> u32 {
+        WIDTH
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,584 @@
+//! Async driver for hosts running tokio, e.g. with a port opened by `tokio-serial`
+//!
+//! Frames are built by [command](crate::epd4in3::command) like for every other driver and written
+//! with [AsyncWrite]. Every answer is awaited with [tokio::time::timeout], a frame which isn't
+//! acknowledged in time is sent again.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let port = tokio_serial::new("/dev/ttyUSB0", 115_200).open_native_async()?;
+//! let mut epd4in3 = EPD4in3Tokio::new(port);
+//! epd4in3.init().await?;
+//!
+//! tokio::select! {
+//!     result = epd4in3.draw_ops(&ops) => result?,
+//!     message = socket.recv() => { /* ... */ }
+//! }
+//! ```
+//!
+//! With [set_window()](EPD4in3Tokio::set_window()) several frames are sent before their answers
+//! arrive, which hides the latency of every single frame.
+//!
+//! A command cancelled by `select!` may leave half a frame on the wire. The module drops it
+//! because of the wrong parity, [handshake()](EPD4in3Tokio::handshake()) brings both sides in
+//! sync again.
+
+use core::cmp;
+use core::convert::Infallible;
+use core::slice;
+use std::io;
+use std::time::Duration;
+use std::vec::Vec;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::{sleep, timeout};
+
+use crate::color::EpdColor;
+use crate::epd4in3::protocol::{PixelOps, ReplyMatcher};
+use crate::epd4in3::{
+    Op, DEFAULT_BACKGROUND_COLOR, DEFAULT_FOREGROUND_COLOR, DEFAULT_RETRIES, HEIGHT, WIDTH,
+};
+use crate::traits::Error;
+
+/// Errors of the tokio driver, there are no pins which could fail
+pub type HostError = Error<io::Error, io::Error, Infallible>;
+
+/// Pause between two handshakes while waiting for the device
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Default for how many frames are sent without waiting for their answers
+pub const DEFAULT_WINDOW: usize = 1;
+/// Number of commands of a frame which are kept in memory at the same time
+const CHUNK_LEN: usize = 256;
+
+/// EPD4in3 driver for tokio which owns the port
+///
+/// WAKE and RST aren't used, the module is only checked with handshakes.
+pub struct EPD4in3Tokio<T> {
+    port: T,
+    bg_color: EpdColor,
+    fg_color: EpdColor,
+    ack_timeout: Duration,
+    ready_timeout: Duration,
+    retries: u8,
+    window: usize,
+}
+
+/// Tokio driver while the display is in deep sleep, see [EPD4in3Tokio::sleep()]
+pub struct EPD4in3TokioSleeping<T> {
+    epd: EPD4in3Tokio<T>,
+}
+
+impl<T> EPD4in3Tokio<T> {
+    /// Creates a new driver, nothing is sent before [init()](EPD4in3Tokio::init())
+    pub fn new(port: T) -> Self {
+        EPD4in3Tokio {
+            port,
+            bg_color: DEFAULT_BACKGROUND_COLOR,
+            fg_color: DEFAULT_FOREGROUND_COLOR,
+            ack_timeout: Duration::from_secs(1),
+            ready_timeout: Duration::from_secs(5),
+            retries: DEFAULT_RETRIES,
+            window: DEFAULT_WINDOW,
+        }
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.port
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.port
+    }
+
+    /// Destroys the driver and returns the port
+    pub fn into_inner(self) -> T {
+        self.port
+    }
+
+    /// Sets how long the device may take to answer a frame, 1 s by default
+    pub fn set_ack_timeout(&mut self, ack_timeout: Duration) {
+        self.ack_timeout = ack_timeout;
+    }
+
+    /// Sets how long [init()](EPD4in3Tokio::init()) waits for the device to answer a handshake,
+    /// 5 s by default
+    pub fn set_ready_timeout(&mut self, ready_timeout: Duration) {
+        self.ready_timeout = ready_timeout;
+    }
+
+    /// Sets how often a frame is sent again when the device doesn't acknowledge it, 9 by default
+    pub fn set_retries(&mut self, retries: u8) {
+        self.retries = retries;
+    }
+
+    /// Sets how many frames may wait for their answers at the same time, 1 by default
+    ///
+    /// The module buffers the frames it hasn't processed yet, so `window` frames have to fit
+    /// into its input buffer. A window of 0 is treated like 1.
+    pub fn set_window(&mut self, window: usize) {
+        self.window = cmp::max(window, 1);
+    }
+
+    pub fn set_background_color(&mut self, color: EpdColor) {
+        self.bg_color = color;
+    }
+
+    pub fn set_foreground_color(&mut self, color: EpdColor) {
+        self.fg_color = color;
+    }
+
+    pub fn width(&self) -> u32 {
+        WIDTH
+    }
+
+    pub fn height(&self) -> u32 {
+        HEIGHT
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> EPD4in3Tokio<T> {
+    /// Waits until the device answers a handshake
+    ///
+    /// Returns `Error::Timeout` if it didn't within the ready timeout.
+    pub async fn init(&mut self) -> Result<(), HostError> {
+        let ready_timeout = self.ready_timeout;
+        let wait = async {
+            while !self.handshake().await? {
+                sleep(POLL_INTERVAL).await;
+            }
+            Ok(())
+        };
+        timeout(ready_timeout, wait)
+            .await
+            .unwrap_or(Err(Error::Timeout))
+    }
+
+    /// Sends a handshake and checks that the device answers it with `OK` in time
+    pub async fn handshake(&mut self) -> Result<bool, HostError> {
+        self.write_op(&Op::Handshake).await?;
+        Ok(self.read_reply().await? == Some(true))
+    }
+
+    /// Sends a single command and waits until the device acknowledges it
+    ///
+    /// Returns `Error::Timeout` if it wasn't acknowledged after all retries, see
+    /// [send_all()](EPD4in3Tokio::send_all()).
+    pub async fn send(&mut self, op: &Op<'_>) -> Result<(), HostError> {
+        self.send_all(slice::from_ref(op)).await
+    }
+
+    /// Sends commands while keeping up to [window](EPD4in3Tokio::set_window()) of them in flight
+    ///
+    /// The answers are matched to the frames in order. When a frame isn't acknowledged, the
+    /// answers of the frames sent after it are drained and everything is sent again starting
+    /// with the failed frame. Settings like [Op::SetColor] are only sent once all frames before
+    /// them are acknowledged and nothing follows them until they are, so a frame sent again is
+    /// drawn with the same settings as the first time. If an answer didn't arrive in time, all input is discarded until
+    /// the line stays quiet for the ack timeout first, so a late answer can't acknowledge the
+    /// frame sent again. Returns `Error::Timeout` if a frame wasn't acknowledged after all
+    /// retries and `Error::Sleep` if the commands contain [Op::Sleep], whose answer is never
+    /// awaited here.
+    pub async fn send_all(&mut self, ops: &[Op<'_>]) -> Result<(), HostError> {
+        if ops.contains(&Op::Sleep) {
+            return Err(Error::Sleep);
+        }
+        self.send_window(ops).await
+    }
+
+    async fn send_window(&mut self, ops: &[Op<'_>]) -> Result<(), HostError> {
+        let (mut acked, mut sent, mut retries) = (0, 0, 0);
+        while acked < ops.len() {
+            while sent < ops.len() && sent - acked < self.window {
+                if sent > acked && (ops[sent].is_setting() || ops[sent - 1].is_setting()) {
+                    break;
+                }
+                self.write_op(&ops[sent]).await?;
+                sent += 1;
+            }
+            let reply = self.read_reply().await?;
+            if reply == Some(true) {
+                // the colors only change once the device knows about them
+                if let Op::SetColor(fg_color, bg_color) = ops[acked] {
+                    self.fg_color = fg_color;
+                    self.bg_color = bg_color;
+                }
+                acked += 1;
+                retries = 0;
+                continue;
+            }
+            let mut timed_out = reply.is_none();
+            for _ in acked + 1..sent {
+                timed_out |= self.read_reply().await?.is_none();
+            }
+            if timed_out {
+                self.discard_input().await?;
+            }
+            if retries == self.retries {
+                return Err(Error::Timeout);
+            }
+            retries += 1;
+            sent = acked;
+        }
+        Ok(())
+    }
+
+    /// Sends a list of commands, see [EPD4in3::draw_ops()](crate::epd4in3::EPD4in3::draw_ops())
+    pub async fn draw_ops(&mut self, ops: &[Op<'_>]) -> Result<(), HostError> {
+        self.send_all(ops).await
+    }
+
+    /// Transmits all pixels which differ from the background color
+    ///
+    /// Sends the same frames as [update_frame()](crate::prelude::WaveshareDisplay::update_frame())
+    /// of the blocking driver. The commands are created and sent in chunks, so only a few of them
+    /// are held in memory.
+    pub async fn update_frame(&mut self, buffer: &[EpdColor]) -> Result<(), HostError> {
+        let mut ops = PixelOps::new(buffer.iter().cloned(), self.fg_color, self.bg_color);
+        let mut chunk = Vec::with_capacity(CHUNK_LEN);
+        loop {
+            chunk.clear();
+            chunk.extend(ops.by_ref().take(CHUNK_LEN));
+            if chunk.is_empty() {
+                return Ok(());
+            }
+            self.send_all(&chunk).await?;
+        }
+    }
+
+    /// Displays the frame data from SRAM
+    pub async fn display_frame(&mut self) -> Result<(), HostError> {
+        self.send(&Op::Refresh).await
+    }
+
+    /// Clears the frame buffer on the EPD with the background color
+    pub async fn clear_frame(&mut self) -> Result<(), HostError> {
+        self.send(&Op::Clear).await
+    }
+
+    /// Lets the device enter deep-sleep mode, see [EPD4in3::sleep()](crate::epd4in3::EPD4in3::sleep())
+    ///
+    /// If the device doesn't acknowledge it, the driver is returned along with the error.
+    pub async fn sleep(mut self) -> Result<EPD4in3TokioSleeping<T>, (Self, HostError)> {
+        match self.send_window(slice::from_ref(&Op::Sleep)).await {
+            Ok(()) => Ok(EPD4in3TokioSleeping { epd: self }),
+            Err(e) => Err((self, e)),
+        }
+    }
+
+    async fn write_op(&mut self, op: &Op<'_>) -> Result<(), HostError> {
+        let frame = op.frame().map_err(|_| Error::Frame)?;
+        self.port
+            .write_all(frame.get_bytes())
+            .await
+            .map_err(Error::SerialW)?;
+        self.port.flush().await.map_err(Error::SerialW)
+    }
+
+    // Drops everything received until nothing arrived for the ack timeout
+    async fn discard_input(&mut self) -> Result<(), HostError> {
+        let mut buffer = [0; 16];
+        loop {
+            match timeout(self.ack_timeout, self.port.read(&mut buffer)).await {
+                Ok(Ok(0)) | Err(_) => return Ok(()),
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => return Err(Error::SerialR(e)),
+            }
+        }
+    }
+
+    // Waits for `OK` or `Error`, returns `None` if neither arrived within the ack timeout
+    //
+    // The end of the input is reported as `io::ErrorKind::UnexpectedEof`
+    async fn read_reply(&mut self) -> Result<Option<bool>, HostError> {
+        let port = &mut self.port;
+        let read = async {
+            let mut matcher = ReplyMatcher::default();
+            let mut byte = [0];
+            loop {
+                if port.read(&mut byte).await? == 0 {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+                }
+                if let Some(ok) = matcher.push(byte[0]) {
+                    return Ok(ok);
+                }
+            }
+        };
+        match timeout(self.ack_timeout, read).await {
+            Ok(reply) => reply.map(Some).map_err(Error::SerialR),
+            Err(_) => Ok(None),
+        }
+    }
+}
+
+impl<T> EPD4in3TokioSleeping<T> {
+    /// Destroys the driver and returns the port
+    pub fn into_inner(self) -> T {
+        self.epd.into_inner()
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> EPD4in3TokioSleeping<T> {
+    /// Waits until the display answers a handshake again and returns the driver
+    ///
+    /// There is no WAKE pin, so the module has to be woken up in another way, e.g. by a reset.
+    /// If it doesn't answer, the sleeping driver is returned along with the error.
+    pub async fn wake_up(self) -> Result<EPD4in3Tokio<T>, (Self, HostError)> {
+        let mut epd = self.epd;
+        match epd.init().await {
+            Ok(()) => Ok(epd),
+            Err(e) => Err((EPD4in3TokioSleeping { epd }, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+    use tokio::io::{duplex, DuplexStream};
+    use tokio::task::JoinHandle;
+
+    // Fake module on the other end of the pipe, answers the frames in order and returns them
+    //
+    // `None` doesn't answer the frame at all. The pipe stays open for a minute afterwards, so
+    // the driver runs into its timeouts instead of the end of the input.
+    fn fake_module(
+        mut port: DuplexStream,
+        replies: Vec<Option<&'static [u8]>>,
+    ) -> JoinHandle<Vec<Vec<u8>>> {
+        tokio::spawn(async move {
+            let mut frames = Vec::new();
+            for reply in replies {
+                frames.push(read_frame(&mut port).await);
+                if let Some(reply) = reply {
+                    port.write_all(reply).await.unwrap();
+                }
+            }
+            sleep(Duration::from_secs(60)).await;
+            frames
+        })
+    }
+
+    async fn read_frame(port: &mut DuplexStream) -> Vec<u8> {
+        let mut header = [0; 3];
+        port.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[0], 0xA5);
+        let mut frame = header.to_vec();
+        frame.resize(usize::from(u16::from_be_bytes([header[1], header[2]])), 0);
+        port.read_exact(&mut frame[3..]).await.unwrap();
+        frame
+    }
+
+    fn frame(op: &Op) -> Vec<u8> {
+        op.frame().unwrap().get_bytes().to_vec()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sends_frames() {
+        let (port, module) = duplex(64);
+        let module = fake_module(
+            module,
+            vec![None, Some(b"OK"), Some(b"OK"), Some(b"OK"), Some(b"OK")],
+        );
+        let mut epd = EPD4in3Tokio::new(port);
+        let mut buffer = [EpdColor::White; 4];
+        buffer[3] = EpdColor::DarkGray;
+
+        epd.init().await.unwrap();
+        epd.update_frame(&buffer).await.unwrap();
+        epd.display_frame().await.unwrap();
+
+        assert_eq!(
+            module.await.unwrap(),
+            [
+                frame(&Op::Handshake),
+                frame(&Op::Handshake),
+                frame(&Op::SetColor(EpdColor::DarkGray, EpdColor::White)),
+                frame(&Op::Point(3, 0)),
+                frame(&Op::Refresh),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_until_acknowledged() {
+        let (port, module) = duplex(64);
+        let module = fake_module(module, vec![Some(b"Error"), None, Some(b"OK"), None, None]);
+        let mut epd = EPD4in3Tokio::new(port);
+
+        epd.send(&Op::Clear).await.unwrap();
+        epd.set_retries(1);
+        match epd.send(&Op::Refresh).await {
+            Err(Error::Timeout) => {}
+            _ => panic!("expected a timeout"),
+        }
+
+        let clear = frame(&Op::Clear);
+        let refresh = frame(&Op::Refresh);
+        assert_eq!(
+            module.await.unwrap(),
+            [
+                clear.clone(),
+                clear.clone(),
+                clear,
+                refresh.clone(),
+                refresh
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn can_be_selected() {
+        let (port, _module) = duplex(64);
+        let mut epd = EPD4in3Tokio::new(port);
+        epd.set_ready_timeout(Duration::from_secs(60));
+
+        tokio::select! {
+            _ = epd.init() => panic!("nobody answered"),
+            _ = sleep(Duration::from_secs(1)) => {}
+        }
+        epd.set_ready_timeout(Duration::from_secs(2));
+        match epd.init().await {
+            Err(Error::Timeout) => {}
+            _ => panic!("expected a timeout"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_frames_in_flight() {
+        let (port, mut module) = duplex(256);
+        let ops = [Op::Point(0, 0), Op::Point(1, 0), Op::Point(2, 0)];
+        let frames: Vec<u8> = ops.iter().flat_map(frame).collect();
+        let expected = frames.clone();
+        // answers only after all frames arrived, which times out without a window
+        let module = tokio::spawn(async move {
+            let mut received = vec![0; expected.len()];
+            module.read_exact(&mut received).await.unwrap();
+            module.write_all(b"OKOKOK").await.unwrap();
+            received
+        });
+        let mut epd = EPD4in3Tokio::new(port);
+        epd.set_window(3);
+        epd.set_retries(0);
+
+        epd.send_all(&ops).await.unwrap();
+        assert_eq!(module.await.unwrap(), frames);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resends_from_failed_frame() {
+        let (port, module) = duplex(256);
+        let ok: Option<&[u8]> = Some(b"OK");
+        let module = fake_module(module, vec![ok, Some(b"Error"), ok, ok, ok, ok, ok]);
+        let ops = [
+            Op::Point(0, 0),
+            Op::Point(1, 0),
+            Op::Point(2, 0),
+            Op::Point(3, 0),
+        ];
+        let mut epd = EPD4in3Tokio::new(port);
+        epd.set_window(3);
+
+        epd.draw_ops(&ops).await.unwrap();
+
+        let sent: Vec<Vec<u8>> = [0, 1, 2, 3, 1, 2, 3]
+            .iter()
+            .map(|&i| frame(&ops[i]))
+            .collect();
+        assert_eq!(module.await.unwrap(), sent);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resends_from_failed_frame_before_color() {
+        let (port, module) = duplex(256);
+        let ok: Option<&[u8]> = Some(b"OK");
+        let module = fake_module(module, vec![Some(b"Error"), ok, ok, ok, ok]);
+        let gray = Op::SetColor(EpdColor::Gray, EpdColor::White);
+        let ops = [Op::Point(0, 0), gray, Op::Point(1, 0), Op::Point(2, 0)];
+        let mut epd = EPD4in3Tokio::new(port);
+        epd.set_window(3);
+
+        epd.draw_ops(&ops).await.unwrap();
+
+        // the color isn't sent along with the failed point, which is drawn in black again
+        let sent: Vec<Vec<u8>> = [0, 0, 1, 2, 3].iter().map(|&i| frame(&ops[i])).collect();
+        assert_eq!(module.await.unwrap(), sent);
+        assert_eq!(epd.fg_color, EpdColor::Gray);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn keeps_colors_of_failed_frames() {
+        let (port, module) = duplex(64);
+        let module = fake_module(module, vec![Some(b"Error"), Some(b"OK"), Some(b"OK")]);
+        let mut epd = EPD4in3Tokio::new(port);
+        epd.set_retries(0);
+        let gray = Op::SetColor(EpdColor::Gray, EpdColor::White);
+        assert!(epd.draw_ops(&[gray, Op::Point(1, 1)]).await.is_err());
+
+        // the device never switched to gray, so the next frame switches again
+        epd.update_frame(&[EpdColor::White, EpdColor::Gray])
+            .await
+            .unwrap();
+        assert_eq!(
+            module.await.unwrap(),
+            [frame(&gray), frame(&gray), frame(&Op::Point(1, 0))]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ignores_late_answers() {
+        let (port, mut module) = duplex(64);
+        let module = tokio::spawn(async move {
+            let mut frames = vec![read_frame(&mut module).await];
+            // answered after the ack timeout, must not acknowledge the frame sent again
+            sleep(Duration::from_millis(1500)).await;
+            module.write_all(b"OK").await.unwrap();
+            for reply in [&b"Error"[..], b"OK", b"OK"].iter() {
+                frames.push(read_frame(&mut module).await);
+                module.write_all(reply).await.unwrap();
+            }
+            frames
+        });
+        let mut epd = EPD4in3Tokio::new(port);
+
+        epd.draw_ops(&[Op::Clear, Op::Refresh]).await.unwrap();
+
+        let (clear, refresh) = (frame(&Op::Clear), frame(&Op::Refresh));
+        assert_eq!(
+            module.await.unwrap(),
+            [clear.clone(), clear.clone(), clear, refresh]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sleeps_and_wakes_up() {
+        let (port, module) = duplex(64);
+        let module = fake_module(module, vec![Some(b"OK"), Some(b"OK")]);
+        let mut epd = EPD4in3Tokio::new(port);
+        match epd.send(&Op::Sleep).await {
+            Err(Error::Sleep) => {}
+            _ => panic!("expected the sleep command to be rejected"),
+        }
+
+        let sleeping = epd.sleep().await.ok().unwrap();
+        sleeping.wake_up().await.ok().unwrap();
+        assert_eq!(
+            module.await.unwrap(),
+            [frame(&Op::Sleep), frame(&Op::Handshake)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reports_end_of_input() {
+        let (port, mut module) = duplex(64);
+        // reads the frame and closes the pipe without answering
+        let module = tokio::spawn(async move { read_frame(&mut module).await });
+        let mut epd = EPD4in3Tokio::new(port);
+
+        match epd.send(&Op::Clear).await {
+            Err(Error::SerialR(e)) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            _ => panic!("expected the end of the input"),
+        }
+        assert_eq!(module.await.unwrap(), frame(&Op::Clear));
+    }
+}
@@ -19,8 +19,9 @@
 //!
 //! ### Other....
 //!
-//! - Buffersize: Wherever a buffer is used it always needs to be of the size: `width / 8 * length`,
-//!   where width and length being either the full e-ink size, since it does not support partial update window size
+//! - Buffersize: Wherever a buffer is used it always needs to be of the size: `width * length`,
+//!   where width and length being either the full e-ink size, since it does not support partial update window size.
+//!   Packed buffers for [update_packed_frame()](EPD4in3::update_packed_frame()) need `width / 4 * length` bytes
 //!
 //! # Examples
 //!
@@ -34,12 +35,12 @@
 //! epd4in3.update_frame(&mut serial, &display.buffer(), &mut delay)?;
 //!
 //! epd4in3.display_frame(&mut serial)?;
-//! 
+//!
 //! // wait and look at the image
 //!
 //! epd4in3.clear_frame(None);
 //!
-//! epd4in3.sleep();
+//! let epd4in3 = epd4in3.sleep(&mut serial).map_err(|(_, e)| e)?;
 //! ```
 //!
 //!
@@ -51,22 +52,77 @@ use embedded_hal::{
     serial::Read,
 };
 
-use crate::color::EpdColor;
+use core::iter::{Enumerate, Peekable};
+
+use crate::adapter::NoPin;
+use crate::color::{self, EpdColor, PIXELS_PER_BYTE};
+#[cfg(feature = "graphics")]
+use crate::graphics::{DirtyTracking, Display, PixelBuffer, Region, VarDisplay};
 use crate::interface::DisplayInterface;
 use crate::traits::{Error, InternalWiAdditions, WaveshareDisplay};
+#[cfg(feature = "graphics")]
+use embedded_graphics::drawable::Pixel;
 
 pub const WIDTH: u32 = 800;
 pub const HEIGHT: u32 = 600;
 pub const DEFAULT_BACKGROUND_COLOR: EpdColor = EpdColor::White;
 pub const DEFAULT_FOREGROUND_COLOR: EpdColor = EpdColor::Black;
+/// How often a frame is sent again when the device doesn't acknowledge it
+pub const DEFAULT_RETRIES: u8 = 9;
 
 pub mod command;
+use self::command::FrameError;
+
+mod shadow;
+pub use self::shadow::{row_hash, Shadow};
+
+mod builder;
+pub use self::builder::{AckMode, EPD4in3Builder};
+
+mod sleeping;
+pub use self::sleeping::EPD4in3Sleeping;
+
+mod owned;
+pub use self::owned::{EPD4in3Owned, EPD4in3OwnedSleeping};
+
+#[cfg(feature = "async")]
+pub mod asynch;
+
+#[cfg(feature = "tokio")]
+pub mod host;
+
+pub(crate) mod protocol;
+
+mod cost;
+pub use self::cost::{CostCounter, UploadCost};
+
+mod op;
+pub use self::op::Op;
+
+mod display_list;
+pub use self::display_list::{DisplayList, OpStorage};
+
+pub mod script;
+
+mod optimize;
+pub use self::optimize::optimize;
+
+mod raster;
+pub use self::raster::Raster;
 
 #[cfg(feature = "graphics")]
 mod graphics;
 #[cfg(feature = "graphics")]
 pub use self::graphics::Display4in3;
 
+/// Result of creating a driver, e.g. by [EPD4in3Builder::build()]
+type DriverResult<T, E, F, G, H> = Result<T, Error<E, F, G, H>>;
+
+/// Result of turning a driver `D` into another one, e.g. by [EPD4in3::sleep()]
+///
+/// On failure the driver is returned along with the error.
+type TransitionResult<T, D, E, F, G, H> = Result<T, (D, Error<E, F, G, H>)>;
+
 /// EPD4in3 driver
 ///
 pub struct EPD4in3<SERIAL, WAKE, RST> {
@@ -76,40 +132,44 @@ pub struct EPD4in3<SERIAL, WAKE, RST> {
     bg_color: EpdColor,
     /// Foreground Color
     fg_color: EpdColor,
+    /// How frames are acknowledged
+    ack_mode: AckMode,
+    /// How often a frame is sent again when it isn't acknowledged
+    retries: u8,
 }
 
-impl<E, F, G, SERIAL, WAKE, RST> InternalWiAdditions<E, F, G, SERIAL, WAKE, RST>
+impl<E, F, G, H, SERIAL, WAKE, RST> InternalWiAdditions<E, F, G, SERIAL, WAKE, RST, H>
     for EPD4in3<SERIAL, WAKE, RST>
 where
     SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
     WAKE: OutputPin<Error = G>,
-    RST: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
 {
     fn init<DELAY: DelayMs<u16>>(
         &mut self,
-        _serial: &mut SERIAL,
+        serial: &mut SERIAL,
         delay: &mut DELAY,
-    ) -> Result<(), Error<E, F, G>> {
+    ) -> Result<(), Error<E, F, G, H>> {
         // reset the device
-        self.interface.reset(delay)
+        self.interface.reset(serial, delay)
     }
 
     fn wake<DELAY: DelayMs<u16>>(
         &mut self,
-        _serial: &mut SERIAL,
+        serial: &mut SERIAL,
         delay: &mut DELAY,
-    ) -> Result<(), Error<E, F, G>> {
+    ) -> Result<(), Error<E, F, G, H>> {
         // wakes the device
-        self.interface.wake(delay)
+        self.interface.wake(serial, delay)
     }
 }
 
-impl<E, F, G, SERIAL, WAKE, RST> WaveshareDisplay<E, F, G, SERIAL, WAKE, RST>
+impl<E, F, G, H, SERIAL, WAKE, RST> WaveshareDisplay<E, F, G, SERIAL, WAKE, RST, H>
     for EPD4in3<SERIAL, WAKE, RST>
 where
     SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
     WAKE: OutputPin<Error = G>,
-    RST: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
 {
     /// Creates a new driver from a SERIAL peripheral, WAKE Pin, RST Pin
     ///
@@ -124,14 +184,121 @@ where
     ///
     /// epd4in3.display_and_transfer_frame(buffer, None);
     ///
-    /// epd4in3.sleep();
+    /// let epd4in3 = epd4in3.sleep(serial).map_err(|(_, e)| e)?;
     /// ```
     fn new<DELAY: DelayMs<u16>>(
         serial: &mut SERIAL,
         wake: WAKE,
         rst: RST,
         delay: &mut DELAY,
-    ) -> Result<Self, Error<E, F, G>> {
+    ) -> Result<Self, Error<E, F, G, H>> {
+        Self::with_pins(serial, Some(wake), Some(rst), delay)
+    }
+
+    fn wake_up<DELAY: DelayMs<u16>>(
+        &mut self,
+        serial: &mut SERIAL,
+        delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>> {
+        self.wake(serial, delay)
+    }
+
+    fn update_frame<DELAY: DelayMs<u16>>(
+        &mut self,
+        serial: &mut SERIAL,
+        buffer: &[EpdColor],
+        _delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>> {
+        self.update_pixels(serial, buffer.iter().copied())
+    }
+
+    fn display_frame(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G, H>> {
+        self.interface.command(serial, command::write_refresh)
+    }
+
+    fn clear_frame(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G, H>> {
+        self.interface.command(serial, command::write_clear)
+    }
+
+    fn set_background_color(&mut self, color: EpdColor) {
+        self.bg_color = color;
+    }
+
+    fn set_foreground_color(&mut self, color: EpdColor) {
+        self.fg_color = color;
+    }
+
+    fn width(&self) -> u32 {
+        WIDTH
+    }
+
+    fn height(&self) -> u32 {
+        HEIGHT
+    }
+}
+
+impl<SERIAL, WAKE, RST> EPD4in3<SERIAL, WAKE, RST> {
+    /// Destroys the driver and returns the pins it was created with
+    ///
+    /// The display is left as it is, e.g. call [sleep()](EPD4in3::sleep()) and
+    /// [release()](EPD4in3Sleeping::release()) before to leave it in deep sleep. The serial port
+    /// is only borrowed by this driver, [EPD4in3Owned::release()] returns it along with the pins.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let (wake, rst) = epd4in3.release();
+    /// let wake = wake.unwrap().into_floating_input();
+    /// ```
+    pub fn release(self) -> (Option<WAKE>, Option<RST>) {
+        self.interface.release()
+    }
+
+    /// Returns a driver in the same state which isn't connected to the display
+    ///
+    /// Any upload done with it and a [CostCounter] runs exactly like on the real display,
+    /// but only counts the frames and bytes which would be sent.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut counter = CostCounter::new();
+    /// epd4in3.dry_run().update_frame(&mut counter, &buffer, &mut delay)?;
+    /// let seconds = counter.cost().duration_ms(115_200).unwrap() / 1000;
+    /// ```
+    pub fn dry_run(&self) -> EPD4in3<CostCounter, NoPin, NoPin> {
+        EPD4in3 {
+            interface: DisplayInterface::new(None, None),
+            bg_color: self.bg_color,
+            fg_color: self.fg_color,
+            ack_mode: self.ack_mode,
+            retries: self.retries,
+        }
+    }
+}
+
+impl<E, F, G, H, SERIAL, WAKE, RST> EPD4in3<SERIAL, WAKE, RST>
+where
+    SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
+    WAKE: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
+{
+    /// Creates a new driver where the WAKE and RST pins may be missing
+    ///
+    /// Without a RST pin the initialisation, and without a WAKE pin [wake_up()](WaveshareDisplay::wake_up()),
+    /// only wait for the device to answer a handshake. They return `Error::Timeout` if it doesn't.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut epd4in3 = EPD4in3::with_pins(serial, None::<NoPin>, Some(rst), delay)?;
+    /// ```
+    pub fn with_pins<DELAY: DelayMs<u16>>(
+        serial: &mut SERIAL,
+        wake: Option<WAKE>,
+        rst: Option<RST>,
+        delay: &mut DELAY,
+    ) -> Result<Self, Error<E, F, G, H>> {
         let interface = DisplayInterface::new(wake, rst);
         let bg_color = DEFAULT_BACKGROUND_COLOR;
         let fg_color = DEFAULT_FOREGROUND_COLOR;
@@ -139,6 +306,8 @@ where
             interface,
             bg_color,
             fg_color,
+            ack_mode: AckMode::Wait,
+            retries: DEFAULT_RETRIES,
         };
 
         epd.init(serial, delay)?;
@@ -146,97 +315,500 @@ where
         Ok(epd)
     }
 
-    fn wake_up<DELAY: DelayMs<u16>>(
+    /// Lets the device enter deep-sleep mode to save power
+    ///
+    /// The driver is turned into an [EPD4in3Sleeping], which can only be woken up again or
+    /// release its pins, so nothing is sent to the device while it ignores it. If the command
+    /// can't be sent, the driver is returned along with the error.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let sleeping = epd4in3.sleep(&mut serial).map_err(|(_, e)| e)?;
+    /// // ...
+    /// let mut epd4in3 = sleeping.wake_up(&mut serial, &mut delay).map_err(|(_, e)| e)?;
+    /// ```
+    pub fn sleep(
+        mut self,
+        serial: &mut SERIAL,
+    ) -> TransitionResult<EPD4in3Sleeping<SERIAL, WAKE, RST>, Self, E, F, G, H> {
+        match self.interface.command(serial, command::write_sleep) {
+            Ok(()) => Ok(EPD4in3Sleeping { epd: self }),
+            Err(e) => Err((self, e)),
+        }
+    }
+
+    /// Sets how long the WAKE and RST pins are held low and high when pulsed, 10 ms by default
+    pub fn set_pulse_width(&mut self, ms: u16) {
+        self.interface.set_pulse_width(ms);
+    }
+
+    /// Sets how long resetting and waking up wait for the device to answer a handshake,
+    /// 5 s by default
+    ///
+    /// Both the time spent waiting for a reply on the serial port and the pauses between the
+    /// handshakes are counted.
+    pub fn set_ready_timeout(&mut self, ms: u32) {
+        self.interface.set_ready_timeout(ms);
+    }
+
+    /// Sends a list of commands, e.g. the ones recorded in a [DisplayList]
+    ///
+    /// Each command is sent again until the device acknowledges it, `Error::Timeout` is
+    /// returned if it didn't after all retries. Color switches also
+    /// change the foreground and background color of the driver. Nothing is sent if the list
+    /// contains [Op::Sleep], which has to go through [sleep()](EPD4in3::sleep()) instead.
+    pub fn draw_ops(&mut self, serial: &mut SERIAL, ops: &[Op]) -> Result<(), Error<E, F, G, H>> {
+        if ops.contains(&Op::Sleep) {
+            return Err(Error::Sleep);
+        }
+        for op in ops.iter() {
+            if let Op::SetColor(fg_color, bg_color) = *op {
+                self.fg_color = fg_color;
+                self.bg_color = bg_color;
+            }
+            self.send_acked(serial, None, |serial| op.write(serial))?;
+        }
+        Ok(())
+    }
+
+    /// Transmit a full frame stored with 4 pixels per byte to the SRAM of the EPD
+    ///
+    /// Same as [update_frame()](WaveshareDisplay::update_frame()) for the buffer of a
+    /// [PackedDisplay](crate::graphics::PackedDisplay), the buffer needs to be of the size
+    /// `width / 4 * height`
+    pub fn update_packed_frame<DELAY: DelayMs<u16>>(
         &mut self,
         serial: &mut SERIAL,
-        delay: &mut DELAY,
-    ) -> Result<(), Error<E, F, G>> {
-        self.wake(serial, delay)
+        buffer: &[u8],
+        _delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>> {
+        let len = (buffer.len() * PIXELS_PER_BYTE).min((WIDTH * HEIGHT) as usize);
+        self.update_pixels(
+            serial,
+            (0..len).map(|index| EpdColor::from_packed(buffer, index)),
+        )
     }
 
-    fn sleep(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G>> {
-        let cmd_sleep = command::sleep().unwrap();
-        self.interface.data(serial, cmd_sleep.get_bytes())?;
+    // Transmits all pixels which differ from the background color, row by row
+    fn update_pixels<I>(&mut self, serial: &mut SERIAL, pixels: I) -> Result<(), Error<E, F, G, H>>
+    where
+        I: IntoIterator<Item = EpdColor>,
+    {
+        for (index, color) in pixels.into_iter().enumerate() {
+            //Skip background pixels
+            if self.bg_color == color {
+                continue;
+            }
+
+            let (x, y) = (index as u32 % self.width(), index as u32 / self.width());
+            self.draw_acked(serial, color, |s| {
+                command::write_point(s, x as u16, y as u16)
+            })?;
+        }
+
         Ok(())
     }
 
-    fn update_frame<DELAY: DelayMs<u16>>(
+    /// Transmits the rows of a part of the frame beginning with `first_row`
+    ///
+    /// The buffer holds `buffer.len() / WIDTH` complete rows. Neighbouring pixels of the same
+    /// color are sent as a single line and background pixels are skipped.
+    pub fn update_rows(
         &mut self,
         serial: &mut SERIAL,
+        first_row: u32,
         buffer: &[EpdColor],
+    ) -> Result<(), Error<E, F, G, H>> {
+        for (y, row) in (first_row..HEIGHT).zip(buffer.chunks_exact(WIDTH as usize)) {
+            self.update_row(serial, y as u16, row.iter().copied())?;
+        }
+        Ok(())
+    }
+
+    /// Transmits a full frame generated row by row
+    ///
+    /// Works like [update_rows()](EPD4in3::update_rows()) without the need for a buffer, e.g.
+    /// for content computed on the fly or decompressed from flash. Rows longer than
+    /// [WIDTH] are cut off, as are rows after [HEIGHT].
+    pub fn update_frame_from_rows<DELAY, R, P>(
+        &mut self,
+        serial: &mut SERIAL,
+        rows: R,
         _delay: &mut DELAY,
-    ) -> Result<(), Error<E, F, G>> {
-        for (index, &color) in buffer.iter().enumerate() {
-            let mut retries = 0;
-            let mut response_ok = false;
-            while (retries < 10) && !response_ok {
-                let mut read_bytes = 0;
-                //Skip background pixels
-                if self.bg_color == color {
-                    break;
-                }
+    ) -> Result<(), Error<E, F, G, H>>
+    where
+        DELAY: DelayMs<u16>,
+        R: IntoIterator<Item = P>,
+        P: IntoIterator<Item = EpdColor>,
+    {
+        for (y, row) in (0..HEIGHT).zip(rows) {
+            self.update_row(serial, y as u16, row.into_iter().take(WIDTH as usize))?;
+        }
+        Ok(())
+    }
 
-                if self.fg_color != color {
-                    self.set_foreground_color(color);
-                    let cmd_color = command::set_color(color, self.bg_color).unwrap();
-                    self.interface.data(serial, cmd_color.get_bytes())?;
-                    read_bytes += 2;
-                }
+    /// Transmits a full frame on top of its most frequent color
+    ///
+    /// Counts the colors of the buffer and fills the whole display with the most frequent
+    /// one. That color becomes the background color, so its pixels are covered by a single
+    /// frame and only the remaining pixels are sent afterwards, which keeps the upload small.
+    /// Returns the chosen background color.
+    pub fn update_frame_auto_background<DELAY: DelayMs<u16>>(
+        &mut self,
+        serial: &mut SERIAL,
+        buffer: &[EpdColor],
+        _delay: &mut DELAY,
+    ) -> Result<EpdColor, Error<E, F, G, H>> {
+        let counts = color::histogram(buffer.iter().copied());
+        let bg_color = color::most_frequent(&counts, self.bg_color);
+        self.set_background_color(bg_color);
 
-                let (x, y) = (index as u32 % self.width(), index as u32 / self.width());
-                let cmd_point = command::point(x as u16, y as u16).unwrap();
-                self.interface.data(serial, cmd_point.get_bytes())?;
-                //delay.delay_ms(20);
-                read_bytes += 2;
+        let (x1, y1) = (WIDTH as u16 - 1, HEIGHT as u16 - 1);
+        self.draw_acked(serial, bg_color, |s| {
+            command::write_fill_rect(s, 0, 0, x1, y1)
+        })?;
+        self.update_rows(serial, 0, buffer)?;
 
-                let mut data = [0u8; 4];
-                if read_bytes > 0 {
-                    response_ok = true;
-                    self.interface
-                        .read_serial(serial, &mut data[0..read_bytes])?;
-                    for &byte in data[0..read_bytes].iter() {
-                        if byte == 0x00 {
-                            response_ok = false;
-                            retries+=1;
-                            //extern crate std;
-                            //std::println!("{:x?}", data);
-                        }
-                    }
-                } else {
-                    retries = 10;
+        Ok(bg_color)
+    }
+
+    /// Transmits only the parts of a full frame which changed since the last upload
+    ///
+    /// The `shadow` describes what is currently shown and is updated along the way. Pixels
+    /// which changed back to the background color are drawn explicitly, so no
+    /// [clear_frame()](WaveshareDisplay::clear_frame()) is needed between updates.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut hashes = [0u32; HEIGHT as usize];
+    /// let mut shadow = Shadow::RowHashes(&mut hashes);
+    ///
+    /// epd4in3.clear_frame(&mut serial)?;
+    /// shadow.reset(DEFAULT_BACKGROUND_COLOR);
+    ///
+    /// loop {
+    ///     // draw the next frame into the display
+    ///     epd4in3.update_frame_diff(&mut serial, display.buffer(), &mut shadow, &mut delay)?;
+    ///     epd4in3.display_frame(&mut serial)?;
+    /// }
+    /// ```
+    pub fn update_frame_diff<DELAY: DelayMs<u16>>(
+        &mut self,
+        serial: &mut SERIAL,
+        buffer: &[EpdColor],
+        shadow: &mut Shadow,
+        _delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>> {
+        for (y, row) in (0..HEIGHT as usize).zip(buffer.chunks_exact(WIDTH as usize)) {
+            if !shadow.row_changed(y, row) {
+                continue;
+            }
+
+            let first = y * WIDTH as usize;
+            // Without the old pixels the whole row is drawn, background included
+            let changed = row
+                .iter()
+                .enumerate()
+                .map(|(x, &color)| match shadow.pixel(first + x) {
+                    Some(old) if old == color => None,
+                    _ => Some(color),
+                });
+            for (x0, x1, color) in Runs::new(changed) {
+                if let Some(color) = color {
+                    self.draw_run(serial, y as u16, x0, x1, color)?;
                 }
-            } //end while
-        } //end for
+            }
+
+            shadow.store_row(y, row);
+        }
 
         Ok(())
     }
 
-    fn display_frame(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G>> {
-        let cmd = command::refresh().unwrap();
-        let bytes = cmd.get_bytes();
-        self.interface.data(serial, bytes)
+    // Transmits one row beginning at `y` with lines for runs of the same color
+    fn update_row<P>(
+        &mut self,
+        serial: &mut SERIAL,
+        y: u16,
+        row: P,
+    ) -> Result<(), Error<E, F, G, H>>
+    where
+        P: Iterator<Item = EpdColor>,
+    {
+        for (x0, x1, color) in Runs::new(row) {
+            //Skip background pixels
+            if self.bg_color == color {
+                continue;
+            }
+            self.draw_run(serial, y, x0, x1, color)?;
+        }
+
+        Ok(())
     }
 
-    fn clear_frame(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G>> {
-        let cmd = command::clear().unwrap();
-        let bytes = cmd.get_bytes();
-        self.interface.data(serial, bytes)
+    // Draws the pixels from `x0` to `x1` of row `y` as a point or a line
+    fn draw_run(
+        &mut self,
+        serial: &mut SERIAL,
+        y: u16,
+        x0: u16,
+        x1: u16,
+        color: EpdColor,
+    ) -> Result<(), Error<E, F, G, H>> {
+        if x0 == x1 {
+            self.draw_acked(serial, color, |s| command::write_point(s, x0, y))
+        } else {
+            self.draw_acked(serial, color, |s| command::write_line(s, x0, y, x1, y))
+        }
     }
 
-    fn set_background_color(&mut self, color: EpdColor) {
-        self.bg_color = color;
+    // Draws a shape in `color`, switching the foreground color before if needed
+    fn draw_acked<C>(
+        &mut self,
+        serial: &mut SERIAL,
+        color: EpdColor,
+        write: C,
+    ) -> Result<(), Error<E, F, G, H>>
+    where
+        C: Fn(&mut SERIAL) -> Result<(), FrameError<F>>,
+    {
+        self.send_acked(serial, Some(color), write)
     }
 
-    fn set_foreground_color(&mut self, color: EpdColor) {
-        self.fg_color = color;
+    // Sends a frame, switching the foreground color to `color` before if given and needed
+    //
+    // Everything is sent again until the device acknowledges it, at most `retries` times.
+    // Fails with `Error::Timeout` if the device didn't acknowledge it after all of them.
+    fn send_acked<C>(
+        &mut self,
+        serial: &mut SERIAL,
+        color: Option<EpdColor>,
+        write: C,
+    ) -> Result<(), Error<E, F, G, H>>
+    where
+        C: Fn(&mut SERIAL) -> Result<(), FrameError<F>>,
+    {
+        let mut retries = 0;
+        loop {
+            let mut read_bytes = 0;
+            if let Some(color) = color.filter(|&color| color != self.fg_color) {
+                self.set_foreground_color(color);
+                let bg_color = self.bg_color;
+                self.interface
+                    .command(serial, |s| command::write_set_color(s, color, bg_color))?;
+                read_bytes += 2;
+            }
+
+            self.interface.command(serial, &write)?;
+            //delay.delay_ms(20);
+            read_bytes += 2;
+
+            if self.ack_mode == AckMode::Ignore {
+                return Ok(());
+            }
+            let mut data = [0u8; 4];
+            self.interface
+                .read_serial(serial, &mut data[0..read_bytes])?;
+            let response_ok = data[0..read_bytes].iter().all(|&byte| byte != 0x00);
+            if response_ok {
+                return Ok(());
+            }
+            if retries >= self.retries {
+                return Err(Error::Timeout);
+            }
+            retries += 1;
+        }
     }
+}
 
-    fn width(&self) -> u32 {
-        WIDTH
+#[cfg(feature = "graphics")]
+impl<E, F, G, H, SERIAL, WAKE, RST> EPD4in3<SERIAL, WAKE, RST>
+where
+    SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
+    WAKE: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
+{
+    /// Transmits only the regions of the display which changed since the last upload
+    ///
+    /// Takes the dirty regions of the display, see [update_regions()](EPD4in3::update_regions()).
+    /// Works for displays holding only a strip of the rows as well.
+    pub fn update_dirty<DELAY, D>(
+        &mut self,
+        serial: &mut SERIAL,
+        display: &mut D,
+        _delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>>
+    where
+        DELAY: DelayMs<u16>,
+        D: DirtyTracking,
+    {
+        let regions = display.take_dirty();
+        let (width, first_row) = (display.width(), display.first_row());
+        self.send_regions(serial, display.buffer(), &regions, width, first_row)
     }
 
-    fn height(&self) -> u32 {
-        HEIGHT
+    /// Transmits the given regions of a full frame
+    ///
+    /// Every region is filled with the background color first, then the remaining pixels
+    /// are drawn on top, so nothing of the former content stays visible.
+    pub fn update_regions<DELAY, B>(
+        &mut self,
+        serial: &mut SERIAL,
+        buffer: &B,
+        regions: &[Region],
+        _delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>>
+    where
+        DELAY: DelayMs<u16>,
+        B: PixelBuffer + ?Sized,
+    {
+        self.send_regions(serial, buffer, regions, WIDTH, 0)
+    }
+
+    // Sends regions of a buffer holding rows of `width` pixels, beginning with `first_row`
+    fn send_regions<B>(
+        &mut self,
+        serial: &mut SERIAL,
+        buffer: &B,
+        regions: &[Region],
+        width: u32,
+        first_row: u32,
+    ) -> Result<(), Error<E, F, G, H>>
+    where
+        B: PixelBuffer + ?Sized,
+    {
+        for region in regions.iter() {
+            let (x0, y0) = (region.x0 as u16, region.y0 as u16);
+            let (x1, y1) = (region.x1 as u16, region.y1 as u16);
+            let bg_color = self.bg_color;
+            self.draw_acked(serial, bg_color, |s| {
+                command::write_fill_rect(s, x0, y0, x1, y1)
+            })?;
+
+            for y in region.y0..=region.y1 {
+                let first = ((y - first_row) * width) as usize;
+                let row = (region.x0..=region.x1).map(|x| buffer.pixel(first + x as usize));
+                for (start, end, color) in Runs::new(row) {
+                    if color != bg_color {
+                        self.draw_run(serial, y as u16, x0 + start, x0 + end, color)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Transmits the pixels of an iterator, e.g. from an embedded-graphics primitive
+    ///
+    /// Pixels outside of the display and pixels in the background color are skipped,
+    /// everything else is sent as single points without the need for a buffer.
+    pub fn update_frame_from_iter<DELAY, I>(
+        &mut self,
+        serial: &mut SERIAL,
+        pixels: I,
+        _delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>>
+    where
+        DELAY: DelayMs<u16>,
+        I: IntoIterator<Item = Pixel<EpdColor>>,
+    {
+        for Pixel(point, color) in pixels {
+            if self.bg_color == color {
+                continue;
+            }
+            let (x, y) = (point.x as u32, point.y as u32);
+            if x >= WIDTH || y >= HEIGHT {
+                continue;
+            }
+            self.draw_acked(serial, color, |s| {
+                command::write_point(s, x as u16, y as u16)
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Renders and transmits a frame one horizontal strip at a time
+    ///
+    /// `draw` is called once for every strip of `buffer.len() / WIDTH` rows. It always draws
+    /// the whole frame, everything outside of the current strip is dropped by the [VarDisplay].
+    /// Each strip is transmitted with points and lines before the next one is drawn, so the
+    /// full frame never exists in memory.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut strip = [DEFAULT_BACKGROUND_COLOR; WIDTH as usize * 40];
+    ///
+    /// epd4in3.update_frame_in_strips(&mut serial, &mut strip, &mut delay, |display| {
+    ///     display.draw(
+    ///         Circle::new(Point::new(400, 300), 100)
+    ///             .stroke(Some(EpdColor::Black))
+    ///             .into_iter(),
+    ///     );
+    /// })?;
+    /// ```
+    pub fn update_frame_in_strips<DELAY, D>(
+        &mut self,
+        serial: &mut SERIAL,
+        buffer: &mut [EpdColor],
+        _delay: &mut DELAY,
+        mut draw: D,
+    ) -> Result<(), Error<E, F, G, H>>
+    where
+        DELAY: DelayMs<u16>,
+        D: FnMut(&mut VarDisplay),
+    {
+        let rows = buffer.len() as u32 / WIDTH;
+        assert!(rows > 0);
+
+        let mut first_row = 0;
+        while first_row < HEIGHT {
+            let strip = &mut buffer[0..(rows.min(HEIGHT - first_row) * WIDTH) as usize];
+            let mut display = VarDisplay::strip(WIDTH, HEIGHT, first_row, strip);
+            display.clear_buffer(self.bg_color);
+            draw(&mut display);
+
+            self.update_rows(serial, first_row, strip)?;
+            first_row += rows;
+        }
+
+        Ok(())
+    }
+}
+
+// Splits pixels into runs of the same value as (first index, last index, value)
+struct Runs<I: Iterator> {
+    pixels: Peekable<Enumerate<I>>,
+}
+
+impl<I: Iterator> Runs<I> {
+    fn new(pixels: I) -> Self {
+        Runs {
+            pixels: pixels.enumerate().peekable(),
+        }
+    }
+}
+
+impl<I> Iterator for Runs<I>
+where
+    I: Iterator,
+    I::Item: PartialEq,
+{
+    type Item = (u16, u16, I::Item);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (first, value) = self.pixels.next()?;
+        let mut last = first;
+        while let Some((index, next_value)) = self.pixels.peek() {
+            if *next_value != value {
+                break;
+            }
+            last = *index;
+            self.pixels.next();
+        }
+        Some((first as u16, last as u16, value))
     }
 }
 
@@ -244,10 +816,596 @@ where
 mod tests {
     use super::*;
 
+    use crate::mock::{MockDelay, MockPin, MockSerial};
+    extern crate std;
+
+    // creates a driver and forgets the handshake sent while initialising it
+    fn connect(
+        serial: &mut MockSerial,
+        delay: &mut MockDelay,
+    ) -> EPD4in3<MockSerial, MockPin, MockPin> {
+        let epd = EPD4in3::new(serial, MockPin::default(), MockPin::default(), delay).unwrap();
+        *serial = MockSerial::default();
+        epd
+    }
+
+    // a black pixel in the first row and a gray one in the second
+    fn two_pixels_expected() -> std::vec::Vec<u8> {
+        let mut expected = command::point(3, 0).unwrap().get_bytes().to_vec();
+        expected.extend_from_slice(
+            command::set_color(EpdColor::Gray, EpdColor::White)
+                .unwrap()
+                .get_bytes(),
+        );
+        expected.extend_from_slice(command::point(5, 1).unwrap().get_bytes());
+        expected
+    }
+
+    #[test]
+    fn update_frame_sends_points() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let mut buffer = [DEFAULT_BACKGROUND_COLOR; WIDTH as usize * 2];
+        buffer[3] = EpdColor::Black;
+        buffer[WIDTH as usize + 5] = EpdColor::Gray;
+        epd.update_frame(&mut serial, &buffer, &mut delay).unwrap();
+
+        assert_eq!(serial.written, two_pixels_expected());
+    }
+
+    #[test]
+    fn update_packed_frame_sends_points() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let mut packed = [DEFAULT_BACKGROUND_COLOR.get_byte_value(); WIDTH as usize / 2];
+        EpdColor::Black.write_packed(&mut packed, 3);
+        EpdColor::Gray.write_packed(&mut packed, WIDTH as usize + 5);
+        epd.update_packed_frame(&mut serial, &packed, &mut delay)
+            .unwrap();
+
+        assert_eq!(serial.written, two_pixels_expected());
+    }
+
+    #[test]
+    fn runs_split_colors() {
+        use EpdColor::*;
+        let row = [White, Black, Black, Black, Gray, White, White];
+        let runs: std::vec::Vec<_> = Runs::new(row.iter().copied()).collect();
+        assert_eq!(
+            runs,
+            [(0, 0, White), (1, 3, Black), (4, 4, Gray), (5, 6, White)]
+        );
+    }
+
+    #[cfg(feature = "graphics")]
+    #[test]
+    fn update_frame_in_strips_works() {
+        use embedded_graphics::prelude::*;
+        use embedded_graphics::primitives::Line;
+
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let mut strip = [DEFAULT_BACKGROUND_COLOR; WIDTH as usize * 40];
+        let mut calls = 0;
+        epd.update_frame_in_strips(&mut serial, &mut strip, &mut delay, |display| {
+            calls += 1;
+            display.draw(
+                Line::new(Point::new(5, 45), Point::new(20, 45)).stroke(Some(EpdColor::Black)),
+            );
+            display.draw(Some(Pixel(Point::new(700, 3), EpdColor::Gray)));
+        })
+        .unwrap();
+
+        assert_eq!(calls, HEIGHT / 40);
+
+        let mut expected = command::set_color(EpdColor::Gray, EpdColor::White)
+            .unwrap()
+            .get_bytes()
+            .to_vec();
+        expected.extend_from_slice(command::point(700, 3).unwrap().get_bytes());
+        expected.extend_from_slice(
+            command::set_color(EpdColor::Black, EpdColor::White)
+                .unwrap()
+                .get_bytes(),
+        );
+        expected.extend_from_slice(command::line(5, 45, 20, 45).unwrap().get_bytes());
+        assert_eq!(serial.written, expected);
+    }
+
+    // a black run and a gray pixel in the first row, a gray run in the second
+    fn stripes(x: u32, y: u32) -> EpdColor {
+        match (x, y) {
+            (2..=4, 0) => EpdColor::Black,
+            (6, 0) | (0..=1, 1) => EpdColor::Gray,
+            _ => EpdColor::White,
+        }
+    }
+
+    fn stripes_expected() -> std::vec::Vec<u8> {
+        let mut expected = command::line(2, 0, 4, 0).unwrap().get_bytes().to_vec();
+        expected.extend_from_slice(
+            command::set_color(EpdColor::Gray, EpdColor::White)
+                .unwrap()
+                .get_bytes(),
+        );
+        expected.extend_from_slice(command::point(6, 0).unwrap().get_bytes());
+        expected.extend_from_slice(command::line(0, 1, 1, 1).unwrap().get_bytes());
+        expected
+    }
+
+    #[test]
+    fn update_rows_sends_runs() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let mut buffer = [DEFAULT_BACKGROUND_COLOR; WIDTH as usize * 2];
+        for (index, pixel) in buffer.iter_mut().enumerate() {
+            *pixel = stripes(index as u32 % WIDTH, index as u32 / WIDTH);
+        }
+        epd.update_rows(&mut serial, 0, &buffer).unwrap();
+
+        assert_eq!(serial.written, stripes_expected());
+    }
+
+    #[test]
+    fn update_frame_from_rows_sends_runs() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        // rows longer than the display, the pixels past its width are cut off
+        let rows = (0..2).map(|y| {
+            (0..WIDTH + 10).map(move |x| {
+                if x >= WIDTH {
+                    EpdColor::Black
+                } else {
+                    stripes(x, y)
+                }
+            })
+        });
+        epd.update_frame_from_rows(&mut serial, rows, &mut delay)
+            .unwrap();
+
+        assert_eq!(serial.written, stripes_expected());
+    }
+
+    #[cfg(feature = "graphics")]
+    #[test]
+    fn update_frame_from_iter_works() {
+        use embedded_graphics::prelude::*;
+
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let pixels = [
+            Pixel(Point::new(1, 2), EpdColor::Black),
+            Pixel(Point::new(3, 4), EpdColor::White),
+            Pixel(Point::new(-1, 4), EpdColor::Black),
+            Pixel(Point::new(800, 4), EpdColor::Black),
+            Pixel(Point::new(799, 599), EpdColor::Black),
+        ];
+        epd.update_frame_from_iter(&mut serial, pixels.iter().copied(), &mut delay)
+            .unwrap();
+
+        let mut expected = command::point(1, 2).unwrap().get_bytes().to_vec();
+        expected.extend_from_slice(command::point(799, 599).unwrap().get_bytes());
+        assert_eq!(serial.written, expected);
+    }
+
+    #[test]
+    fn update_frame_diff_sends_changes() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let mut copy = [EpdColor::Black; WIDTH as usize * 2];
+        let mut shadow = Shadow::Frame(&mut copy);
+        shadow.reset(DEFAULT_BACKGROUND_COLOR);
+
+        let mut buffer = [DEFAULT_BACKGROUND_COLOR; WIDTH as usize * 2];
+        buffer[3] = EpdColor::Black;
+        buffer[4] = EpdColor::Black;
+        epd.update_frame_diff(&mut serial, &buffer, &mut shadow, &mut delay)
+            .unwrap();
+        assert_eq!(
+            serial.written,
+            command::line(3, 0, 4, 0).unwrap().get_bytes()
+        );
+
+        serial.written.clear();
+        buffer[3] = EpdColor::White;
+        buffer[WIDTH as usize + 9] = EpdColor::Black;
+        epd.update_frame_diff(&mut serial, &buffer, &mut shadow, &mut delay)
+            .unwrap();
+
+        let mut expected = command::set_color(EpdColor::White, EpdColor::White)
+            .unwrap()
+            .get_bytes()
+            .to_vec();
+        expected.extend_from_slice(command::point(3, 0).unwrap().get_bytes());
+        expected.extend_from_slice(
+            command::set_color(EpdColor::Black, EpdColor::White)
+                .unwrap()
+                .get_bytes(),
+        );
+        expected.extend_from_slice(command::point(9, 1).unwrap().get_bytes());
+        assert_eq!(serial.written, expected);
+
+        serial.written.clear();
+        epd.update_frame_diff(&mut serial, &buffer, &mut shadow, &mut delay)
+            .unwrap();
+        assert!(serial.written.is_empty());
+    }
+
+    #[test]
+    fn update_frame_diff_with_hashes_sends_rows() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let mut hashes = [0u32; 2];
+        let mut shadow = Shadow::RowHashes(&mut hashes);
+        shadow.reset(DEFAULT_BACKGROUND_COLOR);
+
+        let mut buffer = [DEFAULT_BACKGROUND_COLOR; WIDTH as usize * 2];
+        buffer[WIDTH as usize + 9] = EpdColor::Black;
+        epd.update_frame_diff(&mut serial, &buffer, &mut shadow, &mut delay)
+            .unwrap();
+
+        let mut expected = command::set_color(EpdColor::White, EpdColor::White)
+            .unwrap()
+            .get_bytes()
+            .to_vec();
+        expected.extend_from_slice(command::line(0, 1, 8, 1).unwrap().get_bytes());
+        expected.extend_from_slice(
+            command::set_color(EpdColor::Black, EpdColor::White)
+                .unwrap()
+                .get_bytes(),
+        );
+        expected.extend_from_slice(command::point(9, 1).unwrap().get_bytes());
+        expected.extend_from_slice(
+            command::set_color(EpdColor::White, EpdColor::White)
+                .unwrap()
+                .get_bytes(),
+        );
+        expected.extend_from_slice(command::line(10, 1, 799, 1).unwrap().get_bytes());
+        assert_eq!(serial.written, expected);
+    }
+
+    #[cfg(feature = "graphics")]
+    #[test]
+    fn update_dirty_works() {
+        use crate::graphics::PackedDisplay;
+        use embedded_graphics::prelude::*;
+        use embedded_graphics::primitives::Line;
+
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let mut buffer = [DEFAULT_BACKGROUND_COLOR.get_byte_value(); 800 / 4 * 600];
+        let mut display = PackedDisplay::new(WIDTH, HEIGHT, &mut buffer);
+        display
+            .draw(Line::new(Point::new(10, 20), Point::new(10, 21)).stroke(Some(EpdColor::Black)));
+        display.draw(Some(Pixel(Point::new(11, 21), EpdColor::White)));
+
+        epd.update_dirty(&mut serial, &mut display, &mut delay)
+            .unwrap();
+        assert!(display.dirty_regions().is_empty());
+
+        let mut expected = command::set_color(EpdColor::White, EpdColor::White)
+            .unwrap()
+            .get_bytes()
+            .to_vec();
+        expected.extend_from_slice(command::fill_rect(10, 20, 11, 21).unwrap().get_bytes());
+        expected.extend_from_slice(
+            command::set_color(EpdColor::Black, EpdColor::White)
+                .unwrap()
+                .get_bytes(),
+        );
+        expected.extend_from_slice(command::point(10, 20).unwrap().get_bytes());
+        expected.extend_from_slice(command::point(10, 21).unwrap().get_bytes());
+        assert_eq!(serial.written, expected);
+    }
+
+    #[cfg(feature = "graphics")]
+    #[test]
+    fn update_dirty_sends_strip() {
+        use embedded_graphics::prelude::*;
+
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        // rows 40 to 49 of a display which is narrower than the EPD
+        let mut buffer = [DEFAULT_BACKGROUND_COLOR; 100 * 10];
+        let mut display = VarDisplay::strip(100, 60, 40, &mut buffer);
+        display.draw(Some(Pixel(Point::new(5, 42), EpdColor::Black)));
+        display.draw(Some(Pixel(Point::new(99, 49), EpdColor::Black)));
+        let regions: arrayvec::ArrayVec<[Region; 8]> =
+            display.dirty_regions().iter().cloned().collect();
+
+        epd.update_dirty(&mut serial, &mut display, &mut delay)
+            .unwrap();
+
+        let mut expected = MockSerial::default();
+        let mut reference = connect(&mut expected, &mut delay);
+        let mut frame = [DEFAULT_BACKGROUND_COLOR; (WIDTH * 50) as usize];
+        frame[(42 * WIDTH + 5) as usize] = EpdColor::Black;
+        frame[(49 * WIDTH + 99) as usize] = EpdColor::Black;
+        reference
+            .update_regions(&mut expected, &frame[..], &regions, &mut delay)
+            .unwrap();
+        assert_eq!(serial.written, expected.written);
+        assert!(serial
+            .written
+            .ends_with(command::point(99, 49).unwrap().get_bytes()));
+    }
+
+    #[test]
+    fn update_frame_auto_background_works() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let mut buffer = [EpdColor::Black; WIDTH as usize];
+        buffer[7] = EpdColor::White;
+        let bg_color = epd
+            .update_frame_auto_background(&mut serial, &buffer, &mut delay)
+            .unwrap();
+        assert_eq!(bg_color, EpdColor::Black);
+
+        // black is already the foreground color, no need to switch for the fill
+        let mut expected = command::fill_rect(0, 0, 799, 599)
+            .unwrap()
+            .get_bytes()
+            .to_vec();
+        expected.extend_from_slice(
+            command::set_color(EpdColor::White, EpdColor::Black)
+                .unwrap()
+                .get_bytes(),
+        );
+        expected.extend_from_slice(command::point(7, 0).unwrap().get_bytes());
+        assert_eq!(serial.written, expected);
+    }
+
+    #[test]
+    fn dry_run_matches_upload() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let mut buffer = [EpdColor::White; 3 * WIDTH as usize];
+        buffer[1] = EpdColor::Black;
+        buffer[2] = EpdColor::Black;
+        buffer[WIDTH as usize] = EpdColor::Gray;
+
+        let mut counter = CostCounter::new();
+        epd.dry_run()
+            .update_frame(&mut counter, &buffer, &mut delay)
+            .unwrap();
+        epd.update_frame(&mut serial, &buffer, &mut delay).unwrap();
+
+        let cost = counter.cost();
+        assert_eq!(cost.bytes_written as usize, serial.written.len());
+        assert_eq!(cost.bytes_read as usize, serial.read);
+        assert_eq!(cost.frames(command::Command::Point), 3);
+        assert_eq!(cost.frames(command::Command::SetColor), 1);
+        assert_eq!(cost.total_frames(), 4);
+        assert_eq!(cost.acks(), 4);
+    }
+
+    #[test]
+    fn draw_ops_replays_list() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        let mut list: DisplayList<arrayvec::ArrayVec<[Op; 8]>> = DisplayList::new();
+        list.push(Op::SetColor(EpdColor::White, EpdColor::Black))
+            .unwrap();
+        list.push(Op::Clear).unwrap();
+        list.push(Op::Circle(10, 10, 5)).unwrap();
+
+        // the circle is sent again after an error reply
+        serial.replies.extend(b"OKOK\0\0OK".iter());
+        epd.draw_ops(&mut serial, list.ops()).unwrap();
+        epd.draw_ops(&mut serial, &list.ops()[2..]).unwrap();
+
+        let mut recorded = MockSerial::default();
+        list.write(&mut recorded).unwrap();
+        let mut expected = recorded.written;
+        let circle = command::circle(10, 10, 5).unwrap();
+        expected.extend_from_slice(circle.get_bytes());
+        expected.extend_from_slice(circle.get_bytes());
+        assert_eq!(serial.written, expected);
+        assert_eq!(epd.fg_color, EpdColor::White);
+        assert_eq!(epd.bg_color, EpdColor::Black);
+    }
+
+    #[test]
+    fn draw_ops_rejects_sleep() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        match epd.draw_ops(&mut serial, &[Op::Clear, Op::Sleep]) {
+            Err(Error::Sleep) => {}
+            _ => panic!("expected the sleep command to be rejected"),
+        }
+        assert!(serial.written.is_empty());
+    }
+
     #[test]
     fn epd_size() {
         assert_eq!(WIDTH, 800);
         assert_eq!(HEIGHT, 600);
         assert_eq!(DEFAULT_BACKGROUND_COLOR, EpdColor::White);
     }
+
+    // RST pin which can't be driven
+    struct BrokenPin;
+
+    impl OutputPin for BrokenPin {
+        type Error = &'static str;
+
+        fn set_low(&mut self) -> Result<(), Self::Error> {
+            Err("broken")
+        }
+
+        fn set_high(&mut self) -> Result<(), Self::Error> {
+            Err("broken")
+        }
+    }
+
+    #[test]
+    fn handshakes_without_pins() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd =
+            EPD4in3::with_pins(&mut serial, None::<MockPin>, None::<MockPin>, &mut delay).unwrap();
+        epd.wake_up(&mut serial, &mut delay).unwrap();
+
+        let handshake = command::handshake().unwrap();
+        assert_eq!(serial.written, [handshake.get_bytes(); 2].concat());
+        assert_eq!(delay.total_ms, 0);
+
+        // no answer
+        epd.set_ready_timeout(100);
+        serial.silent = true;
+        match epd.wake_up(&mut serial, &mut delay) {
+            Err(Error::Timeout) => {}
+            _ => panic!("expected a timeout"),
+        }
+        assert_eq!(delay.total_ms, 100);
+    }
+
+    #[test]
+    fn reports_rst_errors() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        match EPD4in3::new(&mut serial, MockPin::default(), BrokenPin, &mut delay) {
+            Err(Error::GpioRst("broken")) => {}
+            _ => panic!("expected an error of the RST pin"),
+        }
+
+        // the WAKE pin is still used for waking up
+        let mut epd = EPD4in3::with_pins(
+            &mut serial,
+            Some(MockPin::default()),
+            None::<BrokenPin>,
+            &mut delay,
+        )
+        .unwrap();
+        epd.wake_up(&mut serial, &mut delay).unwrap();
+        assert_eq!(delay.total_ms, 2 * 10);
+    }
+
+    #[test]
+    fn ready_timeout_includes_answers() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+        delay.total_ms = 0;
+
+        // the device never answers
+        serial.silent = true;
+        epd.set_ready_timeout(50);
+        match epd.wake_up(&mut serial, &mut delay) {
+            Err(Error::Timeout) => {}
+            _ => panic!("expected a timeout"),
+        }
+        assert_eq!(serial.written, command::handshake().unwrap().get_bytes());
+        assert_eq!(delay.total_ms, 2 * 10 + 50);
+    }
+
+    #[test]
+    fn waits_until_ready() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        // the device is busy for the first handshake
+        serial.replies.extend(b"Error".iter());
+        let mut epd = EPD4in3::new(
+            &mut serial,
+            MockPin::default(),
+            MockPin::default(),
+            &mut delay,
+        )
+        .unwrap();
+
+        let handshake = command::handshake().unwrap();
+        assert_eq!(serial.written, [handshake.get_bytes(); 2].concat());
+        assert_eq!(delay.total_ms, 2 * 10 + 20);
+
+        epd.set_pulse_width(100);
+        epd.wake_up(&mut serial, &mut delay).unwrap();
+        assert_eq!(delay.total_ms, 2 * 10 + 20 + 2 * 100);
+    }
+
+    #[test]
+    fn drops_input_before_next_handshake() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+
+        // a second, stale error must not be taken for the answer to the next handshake
+        serial.replies.extend(b"ErrorError".iter());
+        epd.wake_up(&mut serial, &mut delay).unwrap();
+
+        let handshake = command::handshake().unwrap();
+        assert_eq!(serial.written, [handshake.get_bytes(); 2].concat());
+    }
+
+    #[test]
+    fn release_returns_pins() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = connect(&mut serial, &mut delay);
+        epd.wake_up(&mut serial, &mut delay).unwrap();
+
+        let (wake, rst) = epd.release();
+        assert!(!wake.unwrap().high);
+        assert!(!rst.unwrap().high);
+    }
+
+    #[test]
+    fn drives_hal_mocks() {
+        use embedded_hal_mock::eh0::delay::NoopDelay;
+        use embedded_hal_mock::eh0::digital::{Mock as PinMock, State, Transaction};
+        use embedded_hal_mock::eh0::serial::{
+            Mock as SerialMock, Transaction as SerialTransaction,
+        };
+
+        let pulse = [
+            Transaction::set(State::Low),
+            Transaction::set(State::High),
+            Transaction::set(State::Low),
+        ];
+        let mut rst = PinMock::new(&pulse);
+        let mut wake = PinMock::new(&[]);
+        let handshake = command::handshake().unwrap();
+        let mut serial = SerialMock::new(&[
+            SerialTransaction::write_many(handshake.get_bytes()),
+            SerialTransaction::read_many(b"OK"),
+        ]);
+
+        EPD4in3::new(
+            &mut serial,
+            wake.clone(),
+            rst.clone(),
+            &mut NoopDelay::new(),
+        )
+        .unwrap();
+        serial.done();
+        wake.done();
+        rst.done();
+    }
 }
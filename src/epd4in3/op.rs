@@ -0,0 +1,243 @@
+//! Typed device commands
+//!
+//! An [Op] is a command together with its arguments, so lists of commands can be
+//! recorded, inspected and rewritten before they are encoded into frames.
+
+use crate::color::EpdColor;
+use crate::epd4in3::command::{
+    self, Command, Fontsize, Frame, FrameError, FrameSink, Overflow, Rotation, Storage, Unmappable,
+    DEFAULT_REPLACEMENT,
+};
+
+/// A device command with its arguments
+///
+/// Coordinates are the same as for the builders in [command]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op<'a> {
+    Handshake,
+    SetBaud(u32),
+    SetStorage(Storage),
+    LoadFont,
+    LoadBmp,
+    Clear,
+    Refresh,
+    Sleep,
+    SetRotation(Rotation),
+    /// Foreground and background color
+    SetColor(EpdColor, EpdColor),
+    SetFontSizeEn(Fontsize),
+    SetFontSizeZh(Fontsize),
+    Point(u16, u16),
+    Line(u16, u16, u16, u16),
+    Rect(u16, u16, u16, u16),
+    FillRect(u16, u16, u16, u16),
+    /// Center and radius
+    Circle(u16, u16, u16),
+    /// Center and radius
+    FillCircle(u16, u16, u16),
+    Tri(u16, u16, u16, u16, u16, u16),
+    FillTri(u16, u16, u16, u16, u16, u16),
+    /// Text, characters GBK can't encode are replaced with [DEFAULT_REPLACEMENT]
+    Text(u16, u16, &'a str),
+    /// Bitmap with the given filename
+    Bmp(u16, u16, &'a str),
+}
+
+/// Inclusive bounding box of the pixels an [Op] draws
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Extent {
+    /// Doesn't draw anything
+    Nothing,
+    /// Draws somewhere, but it's unknown where
+    Unknown,
+    /// Draws the whole screen
+    Screen,
+    Rect(u16, u16, u16, u16),
+}
+
+impl Extent {
+    fn rect(x0: u16, y0: u16, x1: u16, y1: u16) -> Extent {
+        Extent::Rect(x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1))
+    }
+
+    /// Does `self` cover every pixel of `other`
+    pub(crate) fn covers(self, other: Extent) -> bool {
+        match (self, other) {
+            (_, Extent::Nothing) => true,
+            (Extent::Screen, _) => true,
+            (Extent::Rect(ax0, ay0, ax1, ay1), Extent::Rect(bx0, by0, bx1, by1)) => {
+                ax0 <= bx0 && ay0 <= by0 && ax1 >= bx1 && ay1 >= by1
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<'a> Op<'a> {
+    /// The command sent for this operation
+    pub fn command(&self) -> Command {
+        match *self {
+            Op::Handshake => Command::Handshake,
+            Op::SetBaud(_) => Command::SetBaud,
+            Op::SetStorage(_) => Command::SetStorage,
+            Op::LoadFont => Command::LoadFont,
+            Op::LoadBmp => Command::LoadBmp,
+            Op::Clear => Command::Clear,
+            Op::Refresh => Command::Update,
+            Op::Sleep => Command::Sleep,
+            Op::SetRotation(_) => Command::SetRotation,
+            Op::SetColor(..) => Command::SetColor,
+            Op::SetFontSizeEn(_) => Command::SetFontSizeEn,
+            Op::SetFontSizeZh(_) => Command::SetFontSizeZh,
+            Op::Point(..) => Command::Point,
+            Op::Line(..) => Command::Line,
+            Op::Rect(..) => Command::Rect,
+            Op::FillRect(..) => Command::FillRect,
+            Op::Circle(..) => Command::Circle,
+            Op::FillCircle(..) => Command::FillCircle,
+            Op::Tri(..) => Command::Tri,
+            Op::FillTri(..) => Command::FillTri,
+            Op::Text(..) => Command::Text,
+            Op::Bmp(..) => Command::Bmp,
+        }
+    }
+
+    /// Writes the frame of this operation to `sink`
+    pub fn write<S: FrameSink>(&self, sink: &mut S) -> Result<(), FrameError<S::Error>> {
+        match *self {
+            Op::Handshake => command::write_handshake(sink),
+            Op::SetBaud(baud) => command::write_set_baud(sink, baud),
+            Op::SetStorage(storage) => command::write_set_storage(sink, storage),
+            Op::LoadFont => command::write_load_font(sink),
+            Op::LoadBmp => command::write_load_bmp(sink),
+            Op::Clear => command::write_clear(sink),
+            Op::Refresh => command::write_refresh(sink),
+            Op::Sleep => command::write_sleep(sink),
+            Op::SetRotation(rot) => command::write_set_rotation(sink, rot),
+            Op::SetColor(fg, bg) => command::write_set_color(sink, fg, bg),
+            Op::SetFontSizeEn(size) => command::write_set_font_size_en(sink, size),
+            Op::SetFontSizeZh(size) => command::write_set_font_size_zh(sink, size),
+            Op::Point(x0, y0) => command::write_point(sink, x0, y0),
+            Op::Line(x0, y0, x1, y1) => command::write_line(sink, x0, y0, x1, y1),
+            Op::Rect(x0, y0, x1, y1) => command::write_rect(sink, x0, y0, x1, y1),
+            Op::FillRect(x0, y0, x1, y1) => command::write_fill_rect(sink, x0, y0, x1, y1),
+            Op::Circle(x0, y0, r) => command::write_circle(sink, x0, y0, r),
+            Op::FillCircle(x0, y0, r) => command::write_fill_circle(sink, x0, y0, r),
+            Op::Tri(x0, y0, x1, y1, x2, y2) => command::write_tri(sink, x0, y0, x1, y1, x2, y2),
+            Op::FillTri(x0, y0, x1, y1, x2, y2) => {
+                command::write_fill_tri(sink, x0, y0, x1, y1, x2, y2)
+            }
+            Op::Text(x0, y0, txt) => {
+                command::write_text(sink, x0, y0, txt, Unmappable::Replace(DEFAULT_REPLACEMENT))
+            }
+            Op::Bmp(x0, y0, name) => command::write_bmp(sink, x0, y0, name),
+        }
+    }
+
+    /// Builds the frame of this operation
+    pub fn frame(&self) -> Result<Frame, FrameError<Overflow>> {
+        command::build_frame(|frame| self.write(frame))
+    }
+
+    /// Does the result of this operation depend on the colors set with [Op::SetColor]
+    pub fn uses_color(&self) -> bool {
+        self.extent() != Extent::Nothing
+    }
+
+    /// Pixels drawn by this operation
+    pub(crate) fn extent(&self) -> Extent {
+        match *self {
+            Op::Clear => Extent::Screen,
+            Op::Point(x0, y0) => Extent::rect(x0, y0, x0, y0),
+            Op::Line(x0, y0, x1, y1) | Op::Rect(x0, y0, x1, y1) | Op::FillRect(x0, y0, x1, y1) => {
+                Extent::rect(x0, y0, x1, y1)
+            }
+            Op::Circle(x0, y0, r) | Op::FillCircle(x0, y0, r) => Extent::rect(
+                x0.saturating_sub(r),
+                y0.saturating_sub(r),
+                x0.saturating_add(r),
+                y0.saturating_add(r),
+            ),
+            Op::Tri(x0, y0, x1, y1, x2, y2) | Op::FillTri(x0, y0, x1, y1, x2, y2) => Extent::Rect(
+                x0.min(x1).min(x2),
+                y0.min(y1).min(y2),
+                x0.max(x1).max(x2),
+                y0.max(y1).max(y2),
+            ),
+            Op::Text(..) | Op::Bmp(..) => Extent::Unknown,
+            _ => Extent::Nothing,
+        }
+    }
+
+    /// Pixels this operation overwrites with a single color
+    pub(crate) fn opaque_extent(&self) -> Extent {
+        match *self {
+            Op::Clear | Op::FillRect(..) => self.extent(),
+            _ => Extent::Nothing,
+        }
+    }
+
+    /// Does this operation change how the following ones are drawn or what is shown
+    pub(crate) fn is_barrier(&self) -> bool {
+        matches!(*self, Op::Refresh | Op::Sleep | Op::SetRotation(_))
+    }
+
+    /// Does the device keep a setting of this operation for the following ones
+    #[cfg(feature = "tokio")]
+    pub(crate) fn is_setting(&self) -> bool {
+        matches!(
+            *self,
+            Op::SetBaud(_)
+                | Op::SetStorage(_)
+                | Op::SetRotation(_)
+                | Op::SetColor(..)
+                | Op::SetFontSizeEn(_)
+                | Op::SetFontSizeZh(_)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epd4in3::command::SliceSink;
+
+    #[test]
+    fn write_matches_builders() {
+        let ops = [
+            (Op::Point(3, 4), command::point(3, 4).unwrap()),
+            (
+                Op::FillTri(1, 2, 3, 4, 5, 6),
+                command::fill_tri(1, 2, 3, 4, 5, 6).unwrap(),
+            ),
+            (
+                Op::SetColor(EpdColor::Gray, EpdColor::Black),
+                command::set_color(EpdColor::Gray, EpdColor::Black).unwrap(),
+            ),
+            (Op::Text(0, 1, "ab"), command::text(0, 1, "ab").unwrap()),
+            (Op::Refresh, command::refresh().unwrap()),
+            (Op::SetBaud(9600), command::set_baud(9600).unwrap()),
+        ];
+        for (op, frame) in ops.iter() {
+            let mut buffer = [0; 64];
+            let mut sink = SliceSink::new(&mut buffer);
+            op.write(&mut sink).unwrap();
+            assert_eq!(sink.written(), frame.get_bytes());
+            assert_eq!(op.frame().unwrap().get_bytes(), frame.get_bytes());
+            assert_eq!(frame.get_bytes()[3], op.command() as u8);
+        }
+    }
+
+    #[test]
+    fn extent_covers() {
+        let fill = Op::FillRect(10, 10, 0, 0).opaque_extent();
+        assert!(fill.covers(Op::Line(0, 10, 10, 0).extent()));
+        assert!(fill.covers(Op::Circle(5, 5, 5).extent()));
+        assert!(!fill.covers(Op::Circle(5, 5, 6).extent()));
+        assert!(!fill.covers(Op::Text(0, 0, "a").extent()));
+        assert!(Op::Clear
+            .opaque_extent()
+            .covers(Op::Bmp(0, 0, "A.BMP").extent()));
+        assert_eq!(Op::Line(0, 0, 5, 5).opaque_extent(), Extent::Nothing);
+    }
+}
@@ -0,0 +1,306 @@
+//! Removing redundant commands from a list of [Op]s
+
+use crate::epd4in3::op::{Extent, Op};
+
+/// Optimizes a list of commands without changing what is drawn
+///
+/// - primitives completely overdrawn by a later `FillRect` or `Clear` are dropped
+/// - color switches which don't change the colors or are never used are dropped
+/// - adjacent horizontal or vertical points and lines are merged into one line
+///
+/// Nothing is moved across a `Refresh`, `Sleep` or `SetRotation`. The optimized commands are
+/// moved to the front of `ops`, their number is returned.
+///
+/// # Example
+///
+/// ```ignore
+/// let len = optimize(&mut ops);
+/// for op in ops[..len].iter() {
+///     op.write(&mut serial)?;
+/// }
+/// ```
+pub fn optimize(ops: &mut [Op]) -> usize {
+    let mut len = ops.len();
+    cull_occluded(ops, &mut len);
+    drop_color_switches(ops, &mut len);
+    merge_spans(ops, &mut len);
+    len
+}
+
+fn remove(ops: &mut [Op], len: &mut usize, index: usize) {
+    ops[index..*len].rotate_left(1);
+    *len -= 1;
+}
+
+fn cull_occluded(ops: &mut [Op], len: &mut usize) {
+    let mut i = 0;
+    while i < *len {
+        let extent = ops[i].extent();
+        let occluded = extent != Extent::Nothing
+            && ops[i + 1..*len]
+                .iter()
+                .take_while(|op| !op.is_barrier())
+                .any(|op| op.opaque_extent().covers(extent));
+        if occluded {
+            remove(ops, len, i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+fn drop_color_switches(ops: &mut [Op], len: &mut usize) {
+    // colors on the device, unknown at the start and after sleeping
+    let mut colors = None;
+    // last color switch which wasn't used yet, with the colors before it
+    let mut unused = None;
+    let mut i = 0;
+    while i < *len {
+        match ops[i] {
+            Op::SetColor(fg, bg) => {
+                if let Some((index, previous)) = unused.take() {
+                    remove(ops, len, index);
+                    i -= 1;
+                    colors = previous;
+                }
+                if colors == Some((fg, bg)) {
+                    remove(ops, len, i);
+                    continue;
+                }
+                unused = Some((i, colors));
+                colors = Some((fg, bg));
+            }
+            Op::Sleep => {
+                unused = None;
+                colors = None;
+            }
+            op if op.uses_color() => unused = None,
+            _ => {}
+        }
+        i += 1;
+    }
+}
+
+// a point or a line along one axis: the fixed coordinate and the range of the other
+fn span(op: &Op, horizontal: bool) -> Option<(u16, u16, u16)> {
+    match *op {
+        Op::Point(x, y) if horizontal => Some((y, x, x)),
+        Op::Point(x, y) => Some((x, y, y)),
+        Op::Line(x0, y0, x1, y1) if horizontal && y0 == y1 => Some((y0, x0.min(x1), x0.max(x1))),
+        Op::Line(x0, y0, x1, y1) if !horizontal && x0 == x1 => Some((x0, y0.min(y1), y0.max(y1))),
+        _ => None,
+    }
+}
+
+fn merge(a: &Op, b: &Op) -> Option<Op<'static>> {
+    for &horizontal in [true, false].iter() {
+        if let (Some((a_at, a0, a1)), Some((b_at, b0, b1))) =
+            (span(a, horizontal), span(b, horizontal))
+        {
+            let touching = u32::from(a1) + 1 >= u32::from(b0) && u32::from(b1) + 1 >= u32::from(a0);
+            if a_at == b_at && touching {
+                let (first, last) = (a0.min(b0), a1.max(b1));
+                return Some(if first == last && horizontal {
+                    Op::Point(first, a_at)
+                } else if first == last {
+                    Op::Point(a_at, first)
+                } else if horizontal {
+                    Op::Line(first, a_at, last, a_at)
+                } else {
+                    Op::Line(a_at, first, a_at, last)
+                });
+            }
+        }
+    }
+    None
+}
+
+fn merge_spans(ops: &mut [Op], len: &mut usize) {
+    let mut i = 0;
+    while i + 1 < *len {
+        if let Some(merged) = merge(&ops[i], &ops[i + 1]) {
+            ops[i] = merged;
+            remove(ops, len, i + 1);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::EpdColor::{self, Black, DarkGray, Gray, White};
+    use crate::epd4in3::raster::Raster;
+
+    #[test]
+    fn drops_color_switches() {
+        let mut ops = [
+            Op::SetColor(Black, White),
+            Op::Point(0, 0),
+            Op::SetColor(Black, White),
+            Op::Point(5, 5),
+            Op::SetColor(Gray, White),
+            Op::Refresh,
+            Op::SetColor(Black, White),
+            Op::Point(9, 9),
+        ];
+        let len = optimize(&mut ops);
+        assert_eq!(
+            ops[..len],
+            [
+                Op::SetColor(Black, White),
+                Op::Point(0, 0),
+                Op::Point(5, 5),
+                Op::Refresh,
+                Op::Point(9, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn culls_occluded() {
+        let mut ops = [
+            Op::Text(0, 0, "a"),
+            Op::Circle(10, 10, 5),
+            Op::Point(0, 0),
+            Op::SetColor(Gray, White),
+            Op::FillRect(0, 0, 15, 15),
+            Op::Line(20, 20, 30, 30),
+            Op::Refresh,
+            Op::FillRect(0, 0, 1, 1),
+            Op::Clear,
+        ];
+        let len = optimize(&mut ops);
+        assert_eq!(
+            ops[..len],
+            [
+                Op::Text(0, 0, "a"),
+                Op::SetColor(Gray, White),
+                Op::FillRect(0, 0, 15, 15),
+                Op::Line(20, 20, 30, 30),
+                Op::Refresh,
+                Op::Clear,
+            ]
+        );
+    }
+
+    #[test]
+    fn merges_spans() {
+        let mut ops = [
+            Op::Point(0, 0),
+            Op::Point(1, 0),
+            Op::Line(5, 0, 2, 0),
+            Op::Point(5, 1),
+            Op::Point(5, 2),
+            Op::Line(5, 3, 5, 9),
+            Op::Point(7, 7),
+            Op::Point(7, 7),
+            Op::Point(9, 9),
+        ];
+        let len = optimize(&mut ops);
+        assert_eq!(
+            ops[..len],
+            [
+                Op::Line(0, 0, 5, 0),
+                Op::Line(5, 1, 5, 9),
+                Op::Point(7, 7),
+                Op::Point(9, 9),
+            ]
+        );
+    }
+
+    // xorshift, good enough to generate test cases
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        fn below(&mut self, n: u32) -> u16 {
+            (self.next() % n) as u16
+        }
+
+        fn color(&mut self) -> EpdColor {
+            [Black, DarkGray, Gray, White][self.below(4) as usize]
+        }
+    }
+
+    const W: u16 = 24;
+    const H: u16 = 16;
+
+    fn random_op(rng: &mut Rng) -> Op<'static> {
+        // coordinates slightly larger than the raster to check the clipping
+        let mut c = |n: u16| rng.below(u32::from(n) + 4);
+        match c(20) {
+            0..=5 => Op::Point(c(W), c(H)),
+            6..=7 => Op::Line(c(W), c(H), c(W), c(H)),
+            8 => {
+                let y = c(H);
+                Op::Line(c(W), y, c(W), y)
+            }
+            9 => Op::Rect(c(W), c(H), c(W), c(H)),
+            10..=11 => Op::FillRect(c(W), c(H), c(W), c(H)),
+            12 => Op::Circle(c(W), c(H), c(8)),
+            13 => Op::FillCircle(c(W), c(H), c(8)),
+            14 => Op::Tri(c(W), c(H), c(W), c(H), c(W), c(H)),
+            15 => Op::FillTri(c(W), c(H), c(W), c(H), c(W), c(H)),
+            16 => Op::Clear,
+            17 => Op::Refresh,
+            _ => Op::SetColor(rng.color(), rng.color()),
+        }
+    }
+
+    fn render(ops: &[Op]) -> [EpdColor; (W * H) as usize] {
+        let mut buffer = [White; (W * H) as usize];
+        let mut raster = Raster::new(W, H, &mut buffer);
+        for op in ops.iter() {
+            raster.draw(op);
+        }
+        buffer
+    }
+
+    // positions of the refreshes and the end of the list
+    fn shown<'a>(ops: &'a [Op<'a>]) -> impl Iterator<Item = usize> + 'a {
+        ops.iter()
+            .enumerate()
+            .filter(|(_, op)| **op == Op::Refresh)
+            .map(|(i, _)| i)
+            .chain(core::iter::once(ops.len()))
+    }
+
+    #[test]
+    fn keeps_image() {
+        let mut rng = Rng(0x1234_5678);
+        let mut removed = 0;
+        for _ in 0..500 {
+            let mut ops = [Op::Handshake; 40];
+            let count = 1 + usize::from(rng.below(40));
+            for op in ops[..count].iter_mut() {
+                *op = random_op(&mut rng);
+            }
+            let original = ops;
+            let original = &original[..count];
+
+            let len = optimize(&mut ops[..count]);
+            let optimized = &ops[..len];
+            removed += count - len;
+
+            // every refreshed image and the final one stay the same
+            assert_eq!(shown(optimized).count(), shown(original).count());
+            for (end, optimized_end) in shown(original).zip(shown(optimized)) {
+                assert_eq!(
+                    render(&optimized[..optimized_end])[..],
+                    render(&original[..end])[..],
+                    "{:?}",
+                    original
+                );
+            }
+        }
+        assert!(removed > 0);
+    }
+}
@@ -0,0 +1,265 @@
+//! A driver which owns the serial port
+
+use embedded_hal::{
+    blocking::{delay::DelayMs, serial::Write},
+    digital::v2::OutputPin,
+    serial::Read,
+};
+
+use crate::color::EpdColor;
+use crate::epd4in3::{DriverResult, EPD4in3, EPD4in3Sleeping, Op, TransitionResult};
+use crate::traits::{Error, WaveshareDisplay};
+
+/// EPD4in3 driver which owns the serial port, so it doesn't need to be passed to every call
+///
+/// Methods without a counterpart here are reached with [parts_mut()](EPD4in3Owned::parts_mut()).
+///
+/// # Example
+///
+/// ```ignore
+/// let mut epd4in3 = EPD4in3Owned::new(serial, wake, rst, &mut delay)?;
+///
+/// epd4in3.update_frame(&display.buffer(), &mut delay)?;
+/// epd4in3.display_frame()?;
+///
+/// let (serial, wake, rst) = epd4in3.sleep().map_err(|(_, e)| e)?.release();
+/// ```
+pub struct EPD4in3Owned<SERIAL, WAKE, RST> {
+    epd: EPD4in3<SERIAL, WAKE, RST>,
+    serial: SERIAL,
+}
+
+/// [EPD4in3Owned] while the display is in deep sleep, see [EPD4in3Owned::sleep()]
+pub struct EPD4in3OwnedSleeping<SERIAL, WAKE, RST> {
+    sleeping: EPD4in3Sleeping<SERIAL, WAKE, RST>,
+    serial: SERIAL,
+}
+
+impl<SERIAL, WAKE, RST> EPD4in3Owned<SERIAL, WAKE, RST> {
+    /// Combines a driver with the serial port it is connected to
+    pub fn from_parts(epd: EPD4in3<SERIAL, WAKE, RST>, serial: SERIAL) -> Self {
+        EPD4in3Owned { epd, serial }
+    }
+
+    /// Splits the driver from the serial port again
+    pub fn into_parts(self) -> (EPD4in3<SERIAL, WAKE, RST>, SERIAL) {
+        (self.epd, self.serial)
+    }
+
+    /// Borrows the driver together with the serial port to call it with
+    pub fn parts_mut(&mut self) -> (&mut EPD4in3<SERIAL, WAKE, RST>, &mut SERIAL) {
+        (&mut self.epd, &mut self.serial)
+    }
+
+    /// Borrows the serial port
+    pub fn serial(&self) -> &SERIAL {
+        &self.serial
+    }
+
+    /// Mutably borrows the serial port, e.g. to read what the device sent
+    pub fn serial_mut(&mut self) -> &mut SERIAL {
+        &mut self.serial
+    }
+
+    /// Destroys the driver and returns the serial port and the pins it was created with
+    pub fn release(self) -> (SERIAL, Option<WAKE>, Option<RST>) {
+        let (wake, rst) = self.epd.release();
+        (self.serial, wake, rst)
+    }
+}
+
+impl<E, F, G, H, SERIAL, WAKE, RST> EPD4in3Owned<SERIAL, WAKE, RST>
+where
+    SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
+    WAKE: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
+{
+    /// Creates a new driver from a SERIAL peripheral, WAKE Pin, RST Pin, see
+    /// [EPD4in3::new()](WaveshareDisplay::new())
+    pub fn new<DELAY: DelayMs<u16>>(
+        mut serial: SERIAL,
+        wake: WAKE,
+        rst: RST,
+        delay: &mut DELAY,
+    ) -> DriverResult<Self, E, F, G, H> {
+        let epd = EPD4in3::new(&mut serial, wake, rst, delay)?;
+        Ok(EPD4in3Owned { epd, serial })
+    }
+
+    /// Lets the device enter deep-sleep mode, see [EPD4in3::sleep()]
+    ///
+    /// If the command can't be sent, the driver is returned along with the error.
+    pub fn sleep(
+        self,
+    ) -> TransitionResult<EPD4in3OwnedSleeping<SERIAL, WAKE, RST>, Self, E, F, G, H> {
+        let EPD4in3Owned { epd, mut serial } = self;
+        match epd.sleep(&mut serial) {
+            Ok(sleeping) => Ok(EPD4in3OwnedSleeping { sleeping, serial }),
+            Err((epd, e)) => Err((EPD4in3Owned { epd, serial }, e)),
+        }
+    }
+
+    /// Wakes the device up from sleep
+    pub fn wake_up<DELAY: DelayMs<u16>>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>> {
+        self.epd.wake_up(&mut self.serial, delay)
+    }
+
+    /// Sets the backgroundcolor for various commands like [clear_frame()](EPD4in3Owned::clear_frame())
+    pub fn set_background_color(&mut self, color: EpdColor) {
+        self.epd.set_background_color(color);
+    }
+
+    /// Sets the foregroundcolor for various commands
+    pub fn set_foreground_color(&mut self, color: EpdColor) {
+        self.epd.set_foreground_color(color);
+    }
+
+    /// Get the width of the display
+    pub fn width(&self) -> u32 {
+        self.epd.width()
+    }
+
+    /// Get the height of the display
+    pub fn height(&self) -> u32 {
+        self.epd.height()
+    }
+
+    /// Transmit a full frame to the SRAM of the EPD
+    pub fn update_frame<DELAY: DelayMs<u16>>(
+        &mut self,
+        buffer: &[EpdColor],
+        delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>> {
+        self.epd.update_frame(&mut self.serial, buffer, delay)
+    }
+
+    /// Transmit a full frame stored with 4 pixels per byte, see [EPD4in3::update_packed_frame()]
+    pub fn update_packed_frame<DELAY: DelayMs<u16>>(
+        &mut self,
+        buffer: &[u8],
+        delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>> {
+        self.epd
+            .update_packed_frame(&mut self.serial, buffer, delay)
+    }
+
+    /// Displays the frame data from SRAM
+    pub fn display_frame(&mut self) -> Result<(), Error<E, F, G, H>> {
+        self.epd.display_frame(&mut self.serial)
+    }
+
+    /// Clears the frame buffer on the EPD with the declared background color
+    pub fn clear_frame(&mut self) -> Result<(), Error<E, F, G, H>> {
+        self.epd.clear_frame(&mut self.serial)
+    }
+
+    /// Sends a list of commands, see [EPD4in3::draw_ops()]
+    pub fn draw_ops(&mut self, ops: &[Op]) -> Result<(), Error<E, F, G, H>> {
+        self.epd.draw_ops(&mut self.serial, ops)
+    }
+}
+
+impl<SERIAL, WAKE, RST> EPD4in3OwnedSleeping<SERIAL, WAKE, RST> {
+    /// Destroys the driver and returns the serial port and the pins it was created with
+    pub fn release(self) -> (SERIAL, Option<WAKE>, Option<RST>) {
+        let (wake, rst) = self.sleeping.release();
+        (self.serial, wake, rst)
+    }
+}
+
+impl<E, F, G, H, SERIAL, WAKE, RST> EPD4in3OwnedSleeping<SERIAL, WAKE, RST>
+where
+    SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
+    WAKE: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
+{
+    /// Wakes the display up and returns the driver, see [EPD4in3Sleeping::wake_up()]
+    pub fn wake_up<DELAY: DelayMs<u16>>(
+        self,
+        delay: &mut DELAY,
+    ) -> TransitionResult<EPD4in3Owned<SERIAL, WAKE, RST>, Self, E, F, G, H> {
+        let EPD4in3OwnedSleeping {
+            sleeping,
+            mut serial,
+        } = self;
+        match sleeping.wake_up(&mut serial, delay) {
+            Ok(epd) => Ok(EPD4in3Owned { epd, serial }),
+            Err((sleeping, e)) => Err((EPD4in3OwnedSleeping { sleeping, serial }, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::epd4in3::{command, DEFAULT_BACKGROUND_COLOR};
+    use crate::mock::{MockDelay, MockPin, MockSerial};
+
+    #[test]
+    fn owned_matches_borrowed() {
+        let mut delay = MockDelay::default();
+        let mut epd = EPD4in3Owned::new(
+            MockSerial::default(),
+            MockPin::default(),
+            MockPin::default(),
+            &mut delay,
+        )
+        .unwrap();
+        *epd.serial_mut() = MockSerial::default();
+
+        let mut buffer = [DEFAULT_BACKGROUND_COLOR; 16];
+        buffer[3] = EpdColor::Gray;
+        epd.update_frame(&buffer, &mut delay).unwrap();
+        epd.display_frame().unwrap();
+
+        let (mut borrowed, serial) = epd.into_parts();
+        let mut expected = MockSerial::default();
+        borrowed.set_foreground_color(crate::epd4in3::DEFAULT_FOREGROUND_COLOR);
+        borrowed
+            .update_frame(&mut expected, &buffer, &mut delay)
+            .unwrap();
+        borrowed.display_frame(&mut expected).unwrap();
+        assert_eq!(serial.written, expected.written);
+        assert!(serial
+            .written
+            .ends_with(command::refresh().unwrap().get_bytes()));
+
+        let (serial, wake, rst) = EPD4in3Owned::from_parts(borrowed, serial).release();
+        assert_eq!(serial.written, expected.written);
+        assert!(wake.is_some() && rst.is_some());
+    }
+
+    #[test]
+    fn sleeps_and_wakes_up() {
+        let mut delay = MockDelay::default();
+        let mut epd = EPD4in3Owned::new(
+            MockSerial::default(),
+            MockPin::default(),
+            MockPin::default(),
+            &mut delay,
+        )
+        .unwrap();
+        *epd.serial_mut() = MockSerial::default();
+
+        let sleeping = epd.sleep().ok().unwrap();
+        let mut epd = sleeping.wake_up(&mut delay).ok().unwrap();
+        epd.clear_frame().unwrap();
+
+        let (serial, wake, rst) = epd.sleep().ok().unwrap().release();
+        let (sleep, handshake) = (command::sleep().unwrap(), command::handshake().unwrap());
+        let clear = command::clear().unwrap();
+        let expected = [
+            sleep.get_bytes(),
+            handshake.get_bytes(),
+            clear.get_bytes(),
+            sleep.get_bytes(),
+        ];
+        assert_eq!(serial.written, expected.concat());
+        assert!(wake.is_some() && rst.is_some());
+    }
+}
@@ -0,0 +1,146 @@
+//! Parts of the protocol shared by the drivers
+//!
+//! The blocking driver, `EPD4in3Async` and `EPD4in3Tokio` read from different kinds of ports,
+//! but recognize the answers in the same way. The async drivers also turn pixels into commands
+//! in the same way.
+
+#[cfg(any(feature = "async", feature = "tokio"))]
+use crate::color::EpdColor;
+#[cfg(any(feature = "async", feature = "tokio"))]
+use crate::epd4in3::{Op, WIDTH};
+
+/// Longest answer of the device, `Error`
+const MAX_REPLY_LEN: usize = 5;
+
+/// Recognizes `OK` and `Error` in the bytes received from the device
+///
+/// Anything in front of an answer, e.g. noise on the line, is skipped.
+#[derive(Default)]
+pub(crate) struct ReplyMatcher {
+    reply: [u8; MAX_REPLY_LEN],
+    len: usize,
+}
+
+impl ReplyMatcher {
+    /// Adds a received byte, returns whether the answer was `OK` once it is complete
+    pub(crate) fn push(&mut self, byte: u8) -> Option<bool> {
+        if self.len == MAX_REPLY_LEN {
+            self.reply.copy_within(1.., 0);
+            self.len -= 1;
+        }
+        self.reply[self.len] = byte;
+        self.len += 1;
+
+        let reply = &self.reply[..self.len];
+        let ok = if reply.ends_with(b"OK") {
+            true
+        } else if reply.ends_with(b"Error") {
+            false
+        } else {
+            return None;
+        };
+        self.len = 0;
+        Some(ok)
+    }
+}
+
+/// Turns a frame into the commands of [update_frame()](crate::prelude::WaveshareDisplay::update_frame())
+///
+/// Pixels in the background color are skipped, every other pixel becomes a point preceded by a
+/// color switch whenever its color differs from the foreground color.
+#[cfg(any(feature = "async", feature = "tokio"))]
+pub(crate) struct PixelOps<I> {
+    pixels: I,
+    index: u32,
+    bg_color: EpdColor,
+    fg_color: EpdColor,
+    pending: Option<Op<'static>>,
+}
+
+#[cfg(any(feature = "async", feature = "tokio"))]
+impl<I: Iterator<Item = EpdColor>> PixelOps<I> {
+    pub(crate) fn new(pixels: I, fg_color: EpdColor, bg_color: EpdColor) -> Self {
+        PixelOps {
+            pixels,
+            index: 0,
+            bg_color,
+            fg_color,
+            pending: None,
+        }
+    }
+}
+
+#[cfg(any(feature = "async", feature = "tokio"))]
+impl<I: Iterator<Item = EpdColor>> Iterator for PixelOps<I> {
+    type Item = Op<'static>;
+
+    fn next(&mut self) -> Option<Op<'static>> {
+        if let Some(op) = self.pending.take() {
+            return Some(op);
+        }
+        for color in &mut self.pixels {
+            let index = self.index;
+            self.index += 1;
+            if color == self.bg_color {
+                continue;
+            }
+            let point = Op::Point((index % WIDTH) as u16, (index / WIDTH) as u16);
+            if color == self.fg_color {
+                return Some(point);
+            }
+            self.fg_color = color;
+            self.pending = Some(point);
+            return Some(Op::SetColor(color, self.bg_color));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_replies() {
+        let mut matcher = ReplyMatcher::default();
+        let replies: arrayvec::ArrayVec<[Option<bool>; 16]> =
+            b"\0OKErrorxxOK".iter().map(|&b| matcher.push(b)).collect();
+        assert_eq!(
+            &replies[..],
+            [
+                None,
+                None,
+                Some(true),
+                None,
+                None,
+                None,
+                None,
+                Some(false),
+                None,
+                None,
+                None,
+                Some(true)
+            ]
+        );
+    }
+
+    #[cfg(any(feature = "async", feature = "tokio"))]
+    #[test]
+    fn switches_colors_before_points() {
+        let mut pixels = [EpdColor::White; WIDTH as usize + 3];
+        pixels[1] = EpdColor::Black;
+        pixels[2] = EpdColor::Gray;
+        pixels[WIDTH as usize + 2] = EpdColor::Gray;
+        let ops: arrayvec::ArrayVec<[Op; 8]> =
+            PixelOps::new(pixels.iter().cloned(), EpdColor::Black, EpdColor::White).collect();
+        assert_eq!(
+            &ops[..],
+            [
+                Op::Point(1, 0),
+                Op::SetColor(EpdColor::Gray, EpdColor::White),
+                Op::Point(2, 0),
+                Op::Point(2, 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Software model of how the module draws
+
+use crate::color::EpdColor;
+use crate::epd4in3::op::Op;
+use crate::epd4in3::{DEFAULT_BACKGROUND_COLOR, DEFAULT_FOREGROUND_COLOR};
+
+/// Draws [Op]s into a buffer of `width * height` pixels like the module would
+///
+/// Used to check lists of commands without a display. Text and bitmaps depend on the
+/// fonts and files stored on the module and aren't drawn, neither is the rotation applied.
+pub struct Raster<'a> {
+    width: u16,
+    height: u16,
+    buffer: &'a mut [EpdColor],
+    fg_color: EpdColor,
+    bg_color: EpdColor,
+}
+
+impl<'a> Raster<'a> {
+    /// Creates a raster drawing into `buffer` with the default colors of the module
+    pub fn new(width: u16, height: u16, buffer: &'a mut [EpdColor]) -> Self {
+        assert!(buffer.len() >= usize::from(width) * usize::from(height));
+        Raster {
+            width,
+            height,
+            buffer,
+            fg_color: DEFAULT_FOREGROUND_COLOR,
+            bg_color: DEFAULT_BACKGROUND_COLOR,
+        }
+    }
+
+    /// The drawn pixels
+    pub fn buffer(&self) -> &[EpdColor] {
+        self.buffer
+    }
+
+    /// Draws a single operation
+    pub fn draw(&mut self, op: &Op) {
+        match *op {
+            Op::SetColor(fg, bg) => {
+                self.fg_color = fg;
+                self.bg_color = bg;
+            }
+            Op::Clear => {
+                let bg_color = self.bg_color;
+                for pixel in self.buffer.iter_mut() {
+                    *pixel = bg_color;
+                }
+            }
+            Op::Point(x0, y0) => self.point(x0.into(), y0.into()),
+            Op::Line(x0, y0, x1, y1) => self.line(x0.into(), y0.into(), x1.into(), y1.into()),
+            Op::Rect(x0, y0, x1, y1) => {
+                let (x0, y0, x1, y1) = (x0.into(), y0.into(), x1.into(), y1.into());
+                self.line(x0, y0, x1, y0);
+                self.line(x1, y0, x1, y1);
+                self.line(x1, y1, x0, y1);
+                self.line(x0, y1, x0, y0);
+            }
+            Op::FillRect(x0, y0, x1, y1) => {
+                for y in y0.min(y1)..=y0.max(y1) {
+                    for x in x0.min(x1)..=x0.max(x1) {
+                        self.point(x.into(), y.into());
+                    }
+                }
+            }
+            Op::Circle(x0, y0, r) => self.circle(x0.into(), y0.into(), r.into()),
+            Op::FillCircle(x0, y0, r) => {
+                let (x0, y0, r): (i32, i32, i32) = (x0.into(), y0.into(), r.into());
+                for dy in -r..=r {
+                    for dx in -r..=r {
+                        if dx * dx + dy * dy <= r * r {
+                            self.point(x0 + dx, y0 + dy);
+                        }
+                    }
+                }
+            }
+            Op::Tri(x0, y0, x1, y1, x2, y2) => {
+                self.tri(
+                    (x0.into(), y0.into()),
+                    (x1.into(), y1.into()),
+                    (x2.into(), y2.into()),
+                );
+            }
+            Op::FillTri(x0, y0, x1, y1, x2, y2) => {
+                let a = (i32::from(x0), i32::from(y0));
+                let b = (i32::from(x1), i32::from(y1));
+                let c = (i32::from(x2), i32::from(y2));
+                for y in a.1.min(b.1).min(c.1)..=a.1.max(b.1).max(c.1) {
+                    for x in a.0.min(b.0).min(c.0)..=a.0.max(b.0).max(c.0) {
+                        let e0 = edge(a, b, (x, y));
+                        let e1 = edge(b, c, (x, y));
+                        let e2 = edge(c, a, (x, y));
+                        if (e0 >= 0 && e1 >= 0 && e2 >= 0) || (e0 <= 0 && e1 <= 0 && e2 <= 0) {
+                            self.point(x, y);
+                        }
+                    }
+                }
+                // degenerate triangles still show their outline
+                self.tri(a, b, c);
+            }
+            _ => {}
+        }
+    }
+
+    fn point(&mut self, x: i32, y: i32) {
+        if x >= 0 && y >= 0 && x < i32::from(self.width) && y < i32::from(self.height) {
+            self.buffer[y as usize * usize::from(self.width) + x as usize] = self.fg_color;
+        }
+    }
+
+    // Bresenham
+    fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let (mut x, mut y, mut err) = (x0, y0, dx + dy);
+        loop {
+            self.point(x, y);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    // midpoint circle
+    fn circle(&mut self, x0: i32, y0: i32, r: i32) {
+        let (mut x, mut y, mut err) = (r, 0, 1 - r);
+        while x >= y {
+            for &(dx, dy) in [(x, y), (y, x)].iter() {
+                self.point(x0 + dx, y0 + dy);
+                self.point(x0 - dx, y0 + dy);
+                self.point(x0 + dx, y0 - dy);
+                self.point(x0 - dx, y0 - dy);
+            }
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    fn tri(&mut self, a: (i32, i32), b: (i32, i32), c: (i32, i32)) {
+        self.line(a.0, a.1, b.0, b.1);
+        self.line(b.0, b.1, c.0, c.1);
+        self.line(c.0, c.1, a.0, a.1);
+    }
+}
+
+// which side of the edge from a to b the point p is on
+fn edge(a: (i32, i32), b: (i32, i32), p: (i32, i32)) -> i64 {
+    i64::from(b.0 - a.0) * i64::from(p.1 - a.1) - i64::from(b.1 - a.1) * i64::from(p.0 - a.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::EpdColor::{Black, White};
+
+    #[test]
+    fn draws_primitives() {
+        let mut buffer = [White; 5 * 5];
+        let mut raster = Raster::new(5, 5, &mut buffer);
+        raster.draw(&Op::Line(0, 0, 4, 4));
+        raster.draw(&Op::Point(4, 0));
+        raster.draw(&Op::Text(0, 0, "ignored"));
+        #[rustfmt::skip]
+        let expected = [
+            Black, White, White, White, Black,
+            White, Black, White, White, White,
+            White, White, Black, White, White,
+            White, White, White, Black, White,
+            White, White, White, White, Black,
+        ];
+        assert_eq!(raster.buffer(), &expected[..]);
+    }
+
+    #[test]
+    fn clips_and_clears() {
+        let mut buffer = [White; 4 * 3];
+        let mut raster = Raster::new(4, 3, &mut buffer);
+        raster.draw(&Op::FillCircle(3, 2, 3));
+        raster.draw(&Op::SetColor(White, EpdColor::Gray));
+        raster.draw(&Op::Rect(1, 1, 10, 10));
+        assert_eq!(raster.buffer()[0], White);
+        assert_eq!(raster.buffer()[3], Black);
+        assert_eq!(raster.buffer()[5], White);
+        raster.draw(&Op::Clear);
+        assert!(raster.buffer().iter().all(|&c| c == EpdColor::Gray));
+    }
+}
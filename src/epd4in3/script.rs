@@ -0,0 +1,389 @@
+//! Line oriented scripts describing a screen
+//!
+//! Every line holds one command followed by its arguments, separated by whitespace.
+//! Empty lines and everything after a `#` are ignored, text and filenames containing
+//! whitespace are put in double quotes.
+//!
+//! ```text
+//! # status screen
+//! color black white
+//! clear
+//! font en 48
+//! fill_rect 0 0 799 40
+//! text 10 10 "Status"
+//! bmp 0 100 PIC7.BMP
+//! refresh
+//! ```
+//!
+//! | Command | Arguments |
+//! |---------|-----------|
+//! | `handshake`, `load_font`, `load_bmp`, `clear`, `refresh`, `sleep` | |
+//! | `baud` | baud rate |
+//! | `storage` | `nand` or `sd` |
+//! | `rotation` | `0` or `180` |
+//! | `color` | foreground and background: `black`, `dark_gray`, `gray` or `white` |
+//! | `font` | `en` or `zh` and the size `32`, `48` or `64` |
+//! | `point` | `x y` |
+//! | `line`, `rect`, `fill_rect` | `x0 y0 x1 y1` |
+//! | `circle`, `fill_circle` | `x y r` |
+//! | `tri`, `fill_tri` | `x0 y0 x1 y1 x2 y2` |
+//! | `text` | `x y text` |
+//! | `bmp` | `x y filename` |
+
+use core::fmt;
+use core::str::Lines;
+
+use crate::color::EpdColor;
+use crate::epd4in3::command::{Fontsize, Frame, FrameError, Rotation, Storage, TextError};
+use crate::epd4in3::op::Op;
+
+/// Error in a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    /// Line of the error, starting at 1
+    pub line: usize,
+    pub kind: ScriptErrorKind,
+}
+
+/// What is wrong with a line of a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptErrorKind {
+    UnknownCommand,
+    MissingArgument,
+    UnexpectedArgument,
+    /// Not a number between 0 and 65535
+    InvalidNumber,
+    InvalidColor,
+    InvalidFontSize,
+    InvalidRotation,
+    InvalidStorage,
+    /// A double quote isn't closed on the same line
+    UnterminatedString,
+    /// The text can't be put into a frame
+    Text(TextError),
+    /// The filename of a bitmap is too long or not ASCII
+    Filename,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.kind)
+    }
+}
+
+impl fmt::Display for ScriptErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScriptErrorKind::UnknownCommand => write!(f, "unknown command"),
+            ScriptErrorKind::MissingArgument => write!(f, "missing argument"),
+            ScriptErrorKind::UnexpectedArgument => write!(f, "too many arguments"),
+            ScriptErrorKind::InvalidNumber => write!(f, "invalid number"),
+            ScriptErrorKind::InvalidColor => write!(f, "invalid color"),
+            ScriptErrorKind::InvalidFontSize => write!(f, "invalid font size"),
+            ScriptErrorKind::InvalidRotation => write!(f, "invalid rotation"),
+            ScriptErrorKind::InvalidStorage => write!(f, "invalid storage"),
+            ScriptErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            ScriptErrorKind::Text(TextError::Unmappable(ref chars)) => {
+                write!(f, "characters without GBK encoding: {:?}", chars.as_slice())
+            }
+            ScriptErrorKind::Text(_) => write!(f, "text too long"),
+            ScriptErrorKind::Filename => write!(f, "invalid filename"),
+        }
+    }
+}
+
+/// Parses a script into [Op]s, one per command
+///
+/// Only the syntax is checked, use [frames()](Script::frames()) to also check that every
+/// command fits into a frame.
+///
+/// # Example
+///
+/// ```ignore
+/// for frame in Script::new(SCRIPT).frames() {
+///     serial.bwrite_all(frame?.get_bytes())?;
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Script<'a> {
+    lines: Lines<'a>,
+    line: usize,
+}
+
+impl<'a> Script<'a> {
+    pub fn new(script: &'a str) -> Self {
+        Script {
+            lines: script.lines(),
+            line: 0,
+        }
+    }
+
+    /// Builds the frames of all commands
+    pub fn frames(self) -> Frames<'a> {
+        Frames { script: self }
+    }
+}
+
+impl<'a> Iterator for Script<'a> {
+    type Item = Result<Op<'a>, ScriptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for line in &mut self.lines {
+            self.line += 1;
+            let line_number = self.line;
+            let error = |kind| ScriptError {
+                line: line_number,
+                kind,
+            };
+            match parse_line(line) {
+                Ok(Some(op)) => return Some(Ok(op)),
+                Ok(None) => {}
+                Err(kind) => return Some(Err(error(kind))),
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over the frames of a [Script]
+#[derive(Debug, Clone)]
+pub struct Frames<'a> {
+    script: Script<'a>,
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Result<Frame, ScriptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let op = match self.script.next()? {
+            Ok(op) => op,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(op.frame().map_err(|e| ScriptError {
+            line: self.script.line,
+            kind: match e {
+                FrameError::Text(e) => ScriptErrorKind::Text(e),
+                FrameError::Filename => ScriptErrorKind::Filename,
+                _ => ScriptErrorKind::Text(TextError::TooLong),
+            },
+        }))
+    }
+}
+
+// splits a line into words and quoted strings
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Result<Option<&'a str>, ScriptErrorKind> {
+        let rest = self.rest.trim_start();
+        if rest.is_empty() || rest.starts_with('#') {
+            self.rest = "";
+            return Ok(None);
+        }
+        let (token, rest) = if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted
+                .find('"')
+                .ok_or(ScriptErrorKind::UnterminatedString)?;
+            (&quoted[..end], &quoted[end + 1..])
+        } else {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            (&rest[..end], &rest[end..])
+        };
+        self.rest = rest;
+        Ok(Some(token))
+    }
+
+    fn word(&mut self) -> Result<&'a str, ScriptErrorKind> {
+        self.next()?.ok_or(ScriptErrorKind::MissingArgument)
+    }
+
+    fn number(&mut self) -> Result<u16, ScriptErrorKind> {
+        self.word()?
+            .parse()
+            .map_err(|_| ScriptErrorKind::InvalidNumber)
+    }
+
+    fn color(&mut self) -> Result<EpdColor, ScriptErrorKind> {
+        match self.word()? {
+            "black" => Ok(EpdColor::Black),
+            "dark_gray" => Ok(EpdColor::DarkGray),
+            "gray" => Ok(EpdColor::Gray),
+            "white" => Ok(EpdColor::White),
+            _ => Err(ScriptErrorKind::InvalidColor),
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Result<Option<Op<'_>>, ScriptErrorKind> {
+    let mut tokens = Tokens { rest: line };
+    let command = match tokens.next()? {
+        Some(command) => command,
+        None => return Ok(None),
+    };
+    let t = &mut tokens;
+    let op = match command {
+        "handshake" => Op::Handshake,
+        "load_font" => Op::LoadFont,
+        "load_bmp" => Op::LoadBmp,
+        "clear" => Op::Clear,
+        "refresh" => Op::Refresh,
+        "sleep" => Op::Sleep,
+        "baud" => Op::SetBaud(
+            t.word()?
+                .parse()
+                .map_err(|_| ScriptErrorKind::InvalidNumber)?,
+        ),
+        "storage" => match t.word()? {
+            "nand" => Op::SetStorage(Storage::Nand),
+            "sd" => Op::SetStorage(Storage::MicroSd),
+            _ => return Err(ScriptErrorKind::InvalidStorage),
+        },
+        "rotation" => match t.word()? {
+            "0" => Op::SetRotation(Rotation::Rotation0),
+            "180" => Op::SetRotation(Rotation::Rotation180),
+            _ => return Err(ScriptErrorKind::InvalidRotation),
+        },
+        "color" => Op::SetColor(t.color()?, t.color()?),
+        "font" => {
+            let zh = match t.word()? {
+                "en" => false,
+                "zh" => true,
+                _ => return Err(ScriptErrorKind::InvalidFontSize),
+            };
+            let size = match t.word()? {
+                "32" => Fontsize::Size32,
+                "48" => Fontsize::Size48,
+                "64" => Fontsize::Size64,
+                _ => return Err(ScriptErrorKind::InvalidFontSize),
+            };
+            if zh {
+                Op::SetFontSizeZh(size)
+            } else {
+                Op::SetFontSizeEn(size)
+            }
+        }
+        "point" => Op::Point(t.number()?, t.number()?),
+        "line" => Op::Line(t.number()?, t.number()?, t.number()?, t.number()?),
+        "rect" => Op::Rect(t.number()?, t.number()?, t.number()?, t.number()?),
+        "fill_rect" => Op::FillRect(t.number()?, t.number()?, t.number()?, t.number()?),
+        "circle" => Op::Circle(t.number()?, t.number()?, t.number()?),
+        "fill_circle" => Op::FillCircle(t.number()?, t.number()?, t.number()?),
+        "tri" => Op::Tri(
+            t.number()?,
+            t.number()?,
+            t.number()?,
+            t.number()?,
+            t.number()?,
+            t.number()?,
+        ),
+        "fill_tri" => Op::FillTri(
+            t.number()?,
+            t.number()?,
+            t.number()?,
+            t.number()?,
+            t.number()?,
+            t.number()?,
+        ),
+        "text" => Op::Text(t.number()?, t.number()?, t.word()?),
+        "bmp" => Op::Bmp(t.number()?, t.number()?, t.word()?),
+        _ => return Err(ScriptErrorKind::UnknownCommand),
+    };
+    match tokens.next()? {
+        Some(_) => Err(ScriptErrorKind::UnexpectedArgument),
+        None => Ok(Some(op)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::epd4in3::command;
+
+    const SCRIPT: &str = "# status screen
+color black white
+clear
+
+font en 48
+fill_rect 0 0 799 40 # header
+text 10 10 \"Status: ok\"
+bmp 0 100 PIC7.BMP
+storage sd
+rotation 180
+refresh
+";
+
+    #[test]
+    fn parses_script() {
+        let mut ops = Script::new(SCRIPT);
+        assert_eq!(
+            ops.next(),
+            Some(Ok(Op::SetColor(EpdColor::Black, EpdColor::White)))
+        );
+        assert_eq!(ops.next(), Some(Ok(Op::Clear)));
+        assert_eq!(ops.next(), Some(Ok(Op::SetFontSizeEn(Fontsize::Size48))));
+        assert_eq!(ops.next(), Some(Ok(Op::FillRect(0, 0, 799, 40))));
+        assert_eq!(ops.next(), Some(Ok(Op::Text(10, 10, "Status: ok"))));
+        assert_eq!(ops.next(), Some(Ok(Op::Bmp(0, 100, "PIC7.BMP"))));
+        assert_eq!(ops.next(), Some(Ok(Op::SetStorage(Storage::MicroSd))));
+        assert_eq!(ops.next(), Some(Ok(Op::SetRotation(Rotation::Rotation180))));
+        assert_eq!(ops.next(), Some(Ok(Op::Refresh)));
+        assert_eq!(ops.next(), None);
+    }
+
+    #[test]
+    fn frames_match_builders() {
+        let mut frames = Script::new("circle 1 2 3\n  text 0 0 \"a b\"").frames();
+        assert_eq!(
+            frames.next().unwrap().unwrap().get_bytes(),
+            command::circle(1, 2, 3).unwrap().get_bytes()
+        );
+        assert_eq!(
+            frames.next().unwrap().unwrap().get_bytes(),
+            command::text(0, 0, "a b").unwrap().get_bytes()
+        );
+        assert!(frames.next().is_none());
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        let errors = [
+            ("clear\n\nblink", 3, ScriptErrorKind::UnknownCommand),
+            ("point 1", 1, ScriptErrorKind::MissingArgument),
+            ("refresh now", 1, ScriptErrorKind::UnexpectedArgument),
+            ("\npoint 1 -2", 2, ScriptErrorKind::InvalidNumber),
+            ("point 1 65536", 1, ScriptErrorKind::InvalidNumber),
+            ("color black pink", 1, ScriptErrorKind::InvalidColor),
+            ("font en 12", 1, ScriptErrorKind::InvalidFontSize),
+            ("rotation 90", 1, ScriptErrorKind::InvalidRotation),
+            ("storage usb", 1, ScriptErrorKind::InvalidStorage),
+            ("baud fast", 1, ScriptErrorKind::InvalidNumber),
+            ("text 0 0 \"open", 1, ScriptErrorKind::UnterminatedString),
+        ];
+        for (script, line, kind) in errors.iter() {
+            let error = Script::new(script).find_map(Result::err).unwrap();
+            assert_eq!(error.line, *line, "{}", script);
+            assert_eq!(error.kind, *kind, "{}", script);
+        }
+    }
+
+    #[test]
+    fn reports_frame_errors() {
+        let error = Script::new("clear\nbmp 0 0 VERY_LONG_NAME.BMP")
+            .frames()
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(error.line, 2);
+        assert_eq!(error.kind, ScriptErrorKind::Filename);
+    }
+
+    #[test]
+    fn display_error() {
+        extern crate std;
+        use std::string::ToString;
+
+        let error = Script::new("\n\nblink").next().unwrap().unwrap_err();
+        assert_eq!(error.to_string(), "line 3: unknown command");
+    }
+}
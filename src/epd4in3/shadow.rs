@@ -0,0 +1,168 @@
+//! Memory of the last uploaded frame for differential updates
+
+use crate::color::{EpdColor, PIXELS_PER_BYTE};
+use crate::epd4in3::WIDTH;
+
+/// What is remembered about the frame last uploaded with
+/// [update_frame_diff()](crate::epd4in3::EPD4in3::update_frame_diff())
+///
+/// The shadow needs to describe what is currently shown, so after
+/// [clear_frame()](crate::prelude::WaveshareDisplay::clear_frame()) it needs to be
+/// [reset()](Shadow::reset()) to the background color.
+///
+/// To cover every row of the uploaded frames, `HEIGHT` rows for a full frame, a `Frame` needs
+/// `WIDTH * rows` elements, a `Packed` shadow `WIDTH * rows / 4` bytes and `RowHashes` one
+/// element per row. Rows the shadow doesn't cover are always sent completely.
+pub enum Shadow<'a> {
+    /// A copy of the frame with one pixel per element, only changed pixels are sent
+    Frame(&'a mut [EpdColor]),
+    /// A copy of the frame with 4 pixels per byte, only changed pixels are sent
+    Packed(&'a mut [u8]),
+    /// A hash of every row, changed rows are sent completely
+    ///
+    /// Needs only `HEIGHT` elements, but costs more traffic than a full copy
+    RowHashes(&'a mut [u32]),
+}
+
+impl<'a> Shadow<'a> {
+    /// Assumes a display showing only `color`
+    pub fn reset(&mut self, color: EpdColor) {
+        match self {
+            Shadow::Frame(frame) => {
+                for pixel in frame.iter_mut() {
+                    *pixel = color;
+                }
+            }
+            Shadow::Packed(frame) => {
+                for byte in frame.iter_mut() {
+                    *byte = color.get_byte_value();
+                }
+            }
+            Shadow::RowHashes(hashes) => {
+                let hash = row_hash((0..WIDTH).map(|_| color));
+                for row in hashes.iter_mut() {
+                    *row = hash;
+                }
+            }
+        }
+    }
+
+    /// Returns the pixel at `index` of the last frame, `None` if only hashes are kept
+    pub(crate) fn pixel(&self, index: usize) -> Option<EpdColor> {
+        match self {
+            Shadow::Frame(frame) => frame.get(index).copied(),
+            Shadow::Packed(frame) if index / PIXELS_PER_BYTE < frame.len() => {
+                Some(EpdColor::from_packed(frame, index))
+            }
+            Shadow::Packed(_) | Shadow::RowHashes(_) => None,
+        }
+    }
+
+    /// Remembers `row` as row `y` of the last frame, as far as the shadow covers it
+    pub(crate) fn store_row(&mut self, y: usize, row: &[EpdColor]) {
+        let first = y * WIDTH as usize;
+        match self {
+            Shadow::Frame(frame) => {
+                for (pixel, &color) in frame.iter_mut().skip(first).zip(row.iter()) {
+                    *pixel = color;
+                }
+            }
+            Shadow::Packed(frame) => {
+                let end = frame.len() * PIXELS_PER_BYTE;
+                for (index, &color) in (first..end).zip(row.iter()) {
+                    color.write_packed(frame, index);
+                }
+            }
+            Shadow::RowHashes(hashes) => {
+                if let Some(hash) = hashes.get_mut(y) {
+                    *hash = row_hash(row.iter().copied());
+                }
+            }
+        }
+    }
+
+    /// Checks whether `row` differs from row `y` of the last frame
+    pub(crate) fn row_changed(&self, y: usize, row: &[EpdColor]) -> bool {
+        match self {
+            Shadow::RowHashes(hashes) => hashes.get(y) != Some(&row_hash(row.iter().copied())),
+            _ => {
+                let first = y * WIDTH as usize;
+                (first..)
+                    .zip(row.iter())
+                    .any(|(index, &color)| self.pixel(index) != Some(color))
+            }
+        }
+    }
+}
+
+/// FNV-1a hash of the colors of a row
+pub fn row_hash<I: IntoIterator<Item = EpdColor>>(row: I) -> u32 {
+    row.into_iter().fold(0x811c_9dc5, |hash, color| {
+        (hash ^ u32::from(color.get_bit_value())).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_and_compare_rows() {
+        let mut frame = [EpdColor::Black; WIDTH as usize * 2];
+        let mut packed = [0u8; WIDTH as usize * 2 / PIXELS_PER_BYTE];
+        let mut hashes = [0u32; 2];
+        let mut row = [EpdColor::White; WIDTH as usize];
+
+        for shadow in [
+            Shadow::Frame(&mut frame),
+            Shadow::Packed(&mut packed),
+            Shadow::RowHashes(&mut hashes),
+        ]
+        .iter_mut()
+        {
+            shadow.reset(EpdColor::White);
+            assert!(!shadow.row_changed(1, &row));
+
+            row[7] = EpdColor::Gray;
+            assert!(shadow.row_changed(1, &row));
+
+            shadow.store_row(1, &row);
+            assert!(!shadow.row_changed(1, &row));
+            assert!(shadow.row_changed(0, &row));
+            row[7] = EpdColor::White;
+        }
+    }
+
+    #[test]
+    fn short_shadow_sends_uncovered_rows() {
+        let mut frame = [EpdColor::Black; WIDTH as usize + 3];
+        let mut packed = [0u8; WIDTH as usize / PIXELS_PER_BYTE + 1];
+        let mut hashes = [0u32; 1];
+        let row = [EpdColor::White; WIDTH as usize];
+
+        for shadow in [
+            Shadow::Frame(&mut frame),
+            Shadow::Packed(&mut packed),
+            Shadow::RowHashes(&mut hashes),
+        ]
+        .iter_mut()
+        {
+            shadow.reset(EpdColor::White);
+            assert!(!shadow.row_changed(0, &row));
+
+            // the second row is covered partially or not at all
+            shadow.store_row(1, &row);
+            assert!(shadow.row_changed(1, &row));
+            assert!(shadow.row_changed(5, &row));
+        }
+    }
+
+    #[test]
+    fn row_hash_depends_on_order() {
+        use EpdColor::*;
+        assert_ne!(
+            row_hash([Black, White].iter().copied()),
+            row_hash([White, Black].iter().copied())
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! The driver while the display is in deep sleep
+
+use embedded_hal::{
+    blocking::{delay::DelayMs, serial::Write},
+    digital::v2::OutputPin,
+    serial::Read,
+};
+
+use crate::epd4in3::{EPD4in3, TransitionResult};
+use crate::traits::InternalWiAdditions;
+
+/// EPD4in3 driver while the display is in deep sleep, see [EPD4in3::sleep()]
+///
+/// The display ignores all commands while sleeping, so it has to be woken up before
+/// anything else can be sent.
+pub struct EPD4in3Sleeping<SERIAL, WAKE, RST> {
+    pub(crate) epd: EPD4in3<SERIAL, WAKE, RST>,
+}
+
+impl<SERIAL, WAKE, RST> EPD4in3Sleeping<SERIAL, WAKE, RST> {
+    /// Destroys the driver and returns the pins it was created with, see [EPD4in3::release()]
+    pub fn release(self) -> (Option<WAKE>, Option<RST>) {
+        self.epd.interface.release()
+    }
+}
+
+impl<E, F, G, H, SERIAL, WAKE, RST> EPD4in3Sleeping<SERIAL, WAKE, RST>
+where
+    SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
+    WAKE: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
+{
+    /// Wakes the display up and returns the driver with the colors it had before sleeping
+    ///
+    /// Without a WAKE pin this only waits for the device to answer a handshake. If the display
+    /// doesn't wake up, the sleeping driver is returned along with the error.
+    pub fn wake_up<DELAY: DelayMs<u16>>(
+        self,
+        serial: &mut SERIAL,
+        delay: &mut DELAY,
+    ) -> TransitionResult<EPD4in3<SERIAL, WAKE, RST>, Self, E, F, G, H> {
+        let mut epd = self.epd;
+        match epd.wake(serial, delay) {
+            Ok(()) => Ok(epd),
+            Err(e) => Err((EPD4in3Sleeping { epd }, e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::color::EpdColor;
+    use crate::epd4in3::{command, EPD4in3Builder, Op};
+    use crate::mock::{MockDelay, MockPin, MockSerial};
+    use crate::traits::{Error, WaveshareDisplay};
+
+    #[test]
+    fn sleeps_and_wakes_up() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let epd = EPD4in3Builder::new()
+            .wake(MockPin::default())
+            .colors(EpdColor::Gray, EpdColor::White)
+            .build(&mut serial, &mut delay)
+            .unwrap();
+
+        serial = MockSerial::default();
+        let sleeping = epd.sleep(&mut serial).ok().unwrap();
+        assert_eq!(serial.written, command::sleep().unwrap().get_bytes());
+
+        serial = MockSerial::default();
+        let mut epd = sleeping.wake_up(&mut serial, &mut delay).ok().unwrap();
+        assert_eq!(serial.written, command::handshake().unwrap().get_bytes());
+
+        // still drawing in gray, no color switch needed
+        serial = MockSerial::default();
+        epd.draw_ops(&mut serial, &[Op::Point(1, 1)]).unwrap();
+        assert_eq!(serial.written, command::point(1, 1).unwrap().get_bytes());
+
+        let (wake, rst) = epd.sleep(&mut serial).ok().unwrap().release();
+        assert!(wake.is_some());
+        assert!(rst.is_none());
+    }
+
+    #[test]
+    fn keeps_driver_if_not_woken_up() {
+        let mut serial = MockSerial::default();
+        let mut delay = MockDelay::default();
+        let mut epd = EPD4in3Builder::new()
+            .wake(MockPin::default())
+            .ready_timeout(50)
+            .build(&mut serial, &mut delay)
+            .unwrap();
+        epd.set_foreground_color(EpdColor::Gray);
+        let sleeping = epd.sleep(&mut serial).ok().unwrap();
+
+        // the device never answers
+        serial.silent = true;
+        let sleeping = match sleeping.wake_up(&mut serial, &mut delay) {
+            Err((sleeping, Error::Timeout)) => sleeping,
+            _ => panic!("expected a timeout"),
+        };
+
+        serial.silent = false;
+        let epd = sleeping.wake_up(&mut serial, &mut delay).ok().unwrap();
+        assert_eq!(epd.fg_color, EpdColor::Gray);
+    }
+}
@@ -1,6 +1,7 @@
 //! Graphics Support for EPDs
 
-use crate::color::EpdColor;
+use crate::color::{EpdColor, PIXELS_PER_BYTE};
+use arrayvec::ArrayVec;
 use embedded_graphics::prelude::*;
 
 /// DisplayRotation
@@ -22,19 +23,204 @@ impl Default for DisplayRotation {
     }
 }
 
+/// Storage for the pixels of a [Display]
+///
+/// Implemented for `[EpdColor]` with one pixel per element and for `[u8]` with four pixels
+/// packed into each byte (see [EpdColor::from_packed()](EpdColor::from_packed()))
+pub trait PixelBuffer {
+    /// Returns the color of the pixel at `index`
+    fn pixel(&self, index: usize) -> EpdColor;
+
+    /// Sets the color of the pixel at `index`
+    fn set_pixel(&mut self, index: usize, color: EpdColor);
+
+    /// Sets all pixels to `color`
+    fn fill(&mut self, color: EpdColor);
+}
+
+impl PixelBuffer for [EpdColor] {
+    fn pixel(&self, index: usize) -> EpdColor {
+        self[index]
+    }
+
+    fn set_pixel(&mut self, index: usize, color: EpdColor) {
+        self[index] = color;
+    }
+
+    fn fill(&mut self, color: EpdColor) {
+        for elem in self.iter_mut() {
+            *elem = color;
+        }
+    }
+}
+
+impl PixelBuffer for [u8] {
+    fn pixel(&self, index: usize) -> EpdColor {
+        EpdColor::from_packed(self, index)
+    }
+
+    fn set_pixel(&mut self, index: usize, color: EpdColor) {
+        color.write_packed(self, index);
+    }
+
+    fn fill(&mut self, color: EpdColor) {
+        for elem in self.iter_mut() {
+            *elem = color.get_byte_value();
+        }
+    }
+}
+
+/// Maximum number of regions kept by [DirtyRegions]
+pub const MAX_DIRTY_REGIONS: usize = 8;
+
+/// A rectangle of the buffer, the corners are included
+///
+/// Uses the coordinates of the buffer, so the rotation of the display doesn't matter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x0: u32,
+    pub y0: u32,
+    pub x1: u32,
+    pub y1: u32,
+}
+
+impl Region {
+    pub fn new(x0: u32, y0: u32, x1: u32, y1: u32) -> Self {
+        Region { x0, y0, x1, y1 }
+    }
+
+    /// Number of pixels inside the region
+    pub fn area(&self) -> u32 {
+        (self.x1 - self.x0 + 1) * (self.y1 - self.y0 + 1)
+    }
+
+    /// Smallest region containing both regions
+    pub fn union(&self, other: &Region) -> Region {
+        Region {
+            x0: self.x0.min(other.x0),
+            y0: self.y0.min(other.y0),
+            x1: self.x1.max(other.x1),
+            y1: self.y1.max(other.y1),
+        }
+    }
+
+    /// Checks whether `other` lies completely inside of this region
+    pub fn contains(&self, other: &Region) -> bool {
+        self.x0 <= other.x0 && self.y0 <= other.y0 && self.x1 >= other.x1 && self.y1 >= other.y1
+    }
+
+    // Checks whether the regions overlap or are next to each other
+    fn touches(&self, other: &Region) -> bool {
+        self.x0 <= other.x1 + 1
+            && other.x0 <= self.x1 + 1
+            && self.y0 <= other.y1 + 1
+            && other.y0 <= self.y1 + 1
+    }
+}
+
+/// Tracks the changed parts of a display as a few rectangles
+///
+/// Regions which touch are merged. When more than `limit` regions would be needed, the new
+/// region is merged with the one growing the least, so the regions may cover unchanged
+/// pixels as well.
+#[derive(Debug, Clone)]
+pub struct DirtyRegions {
+    width: u32,
+    height: u32,
+    limit: usize,
+    regions: ArrayVec<[Region; MAX_DIRTY_REGIONS]>,
+}
+
+impl DirtyRegions {
+    /// Creates a tracker without any changed regions for a `width` x `height` buffer
+    pub fn new(width: u32, height: u32) -> Self {
+        DirtyRegions {
+            width,
+            height,
+            limit: MAX_DIRTY_REGIONS,
+            regions: ArrayVec::new(),
+        }
+    }
+
+    /// Sets the maximum number of regions, between 1 and [MAX_DIRTY_REGIONS]
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.clamp(1, MAX_DIRTY_REGIONS);
+        while self.regions.len() > self.limit {
+            let region = self.regions.pop().unwrap();
+            self.add(region);
+        }
+    }
+
+    /// Returns the changed regions
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    /// Returns the changed regions and forgets them
+    pub fn take(&mut self) -> ArrayVec<[Region; MAX_DIRTY_REGIONS]> {
+        core::mem::replace(&mut self.regions, ArrayVec::new())
+    }
+
+    /// Marks a single pixel as changed
+    pub fn add_pixel(&mut self, x: u32, y: u32) {
+        self.add(Region::new(x, y, x, y));
+    }
+
+    /// Marks the whole buffer as changed
+    pub fn add_all(&mut self) {
+        self.regions.clear();
+        self.regions
+            .push(Region::new(0, 0, self.width - 1, self.height - 1));
+    }
+
+    /// Marks a region as changed
+    pub fn add(&mut self, region: Region) {
+        if self.regions.iter().any(|r| r.contains(&region)) {
+            return;
+        }
+
+        let mut region = region;
+        loop {
+            if let Some(index) = self.regions.iter().position(|r| r.touches(&region)) {
+                region = region.union(&self.regions.swap_remove(index));
+            } else if self.regions.len() < self.limit {
+                self.regions.push(region);
+                return;
+            } else {
+                let growth = |r: &Region| r.union(&region).area() - r.area();
+                let index = (0..self.regions.len())
+                    .min_by_key(|&index| growth(&self.regions[index]))
+                    .unwrap();
+                region = region.union(&self.regions.swap_remove(index));
+            }
+        }
+    }
+}
+
 pub trait Display: Drawing<EpdColor> {
+    /// The storage of the pixels
+    type Buffer: PixelBuffer + ?Sized;
+
     /// Clears the buffer of the display with the chosen background color
     fn clear_buffer(&mut self, background_color: EpdColor) {
-        for elem in self.get_mut_buffer().iter_mut() {
-            *elem = background_color;
+        self.get_mut_buffer().fill(background_color);
+        if let Some(dirty) = self.dirty_tracker() {
+            dirty.add_all();
         }
     }
 
+    /// Returns the tracker which drawing marks the changed pixels in
+    ///
+    /// Displays implementing [DirtyTracking] return their tracker, the others `None`.
+    fn dirty_tracker(&mut self) -> Option<&mut DirtyRegions> {
+        None
+    }
+
     /// Returns the buffer
-    fn buffer(&self) -> &[EpdColor];
+    fn buffer(&self) -> &Self::Buffer;
 
     /// Returns a mutable buffer
-    fn get_mut_buffer(&mut self) -> &mut [EpdColor];
+    fn get_mut_buffer(&mut self) -> &mut Self::Buffer;
 
     /// Sets the rotation of the display
     fn set_rotation(&mut self, rotation: DisplayRotation);
@@ -50,7 +236,6 @@ pub trait Display: Drawing<EpdColor> {
         T: IntoIterator<Item = Pixel<EpdColor>>,
     {
         let rotation = self.rotation();
-        let buffer = self.get_mut_buffer();
         for Pixel(point, color) in item_pixels {
             if outside_display(point.x as u32, point.y as u32, width, height, rotation) {
                 continue;
@@ -58,11 +243,47 @@ pub trait Display: Drawing<EpdColor> {
 
             // Give us index inside the buffer and the bit-position in that u8 which needs to be changed
             let index = find_position(point.x as u32, point.y as u32, width, height, rotation);
-            buffer[index] = color;
+            self.get_mut_buffer().set_pixel(index, color);
+            if let Some(dirty) = self.dirty_tracker() {
+                dirty.add_pixel(index as u32 % width, index as u32 / width);
+            }
         }
     }
 }
 
+/// A [Display] which keeps track of the changed parts of its buffer
+///
+/// Used by [update_dirty()](crate::epd4in3::EPD4in3::update_dirty()) to upload only those.
+pub trait DirtyTracking: Display {
+    /// Returns the tracker of the changed parts of the buffer
+    fn dirty(&self) -> &DirtyRegions;
+
+    /// Returns a mutable tracker of the changed parts of the buffer
+    fn get_mut_dirty(&mut self) -> &mut DirtyRegions;
+
+    /// Returns the number of pixels in a row of the buffer
+    fn width(&self) -> u32;
+
+    /// Returns the first row of the display which is held in the buffer
+    ///
+    /// Only displays holding a horizontal strip start at another row than 0
+    fn first_row(&self) -> u32 {
+        0
+    }
+
+    /// Returns the regions changed since the last [take_dirty()](DirtyTracking::take_dirty())
+    ///
+    /// A new display starts without any changed regions
+    fn dirty_regions(&self) -> &[Region] {
+        self.dirty().regions()
+    }
+
+    /// Returns the changed regions and forgets them
+    fn take_dirty(&mut self) -> ArrayVec<[Region; MAX_DIRTY_REGIONS]> {
+        self.get_mut_dirty().take()
+    }
+}
+
 /// A variable Display without a predefined buffer
 ///
 /// The buffer can be created as following:
@@ -92,8 +313,10 @@ pub trait Display: Drawing<EpdColor> {
 pub struct VarDisplay<'a> {
     width: u32,
     height: u32,
+    first_row: u32,
     rotation: DisplayRotation,
     buffer: &'a mut [EpdColor],
+    dirty: DirtyRegions,
 }
 
 impl<'a> VarDisplay<'a> {
@@ -103,10 +326,45 @@ impl<'a> VarDisplay<'a> {
         VarDisplay {
             width,
             height,
+            first_row: 0,
+            rotation: DisplayRotation::default(),
+            buffer,
+            dirty: DirtyRegions::new(width, height),
+        }
+    }
+
+    /// Creates a display for a horizontal strip of a `width` x `height` display
+    ///
+    /// The buffer holds `buffer.len() / width` rows beginning with `first_row`. Everything is
+    /// drawn in the coordinates of the whole display, pixels outside of the strip are dropped.
+    pub fn strip(
+        width: u32,
+        height: u32,
+        first_row: u32,
+        buffer: &'a mut [EpdColor],
+    ) -> VarDisplay<'a> {
+        let len = buffer.len() as u32;
+        assert_eq!(len % width, 0);
+        assert!(first_row + len / width <= height);
+        VarDisplay {
+            width,
+            height,
+            first_row,
             rotation: DisplayRotation::default(),
             buffer,
+            dirty: DirtyRegions::new(width, height),
         }
     }
+
+    /// Returns the first row of the display which is held in the buffer
+    pub fn first_row(&self) -> u32 {
+        self.first_row
+    }
+
+    /// Returns the number of rows held in the buffer
+    pub fn rows(&self) -> u32 {
+        self.buffer.len() as u32 / self.width
+    }
 }
 
 impl<'a> Drawing<EpdColor> for VarDisplay<'a> {
@@ -114,11 +372,30 @@ impl<'a> Drawing<EpdColor> for VarDisplay<'a> {
     where
         T: IntoIterator<Item = Pixel<EpdColor>>,
     {
-        self.draw_helper(self.width, self.height, item_pixels);
+        let (width, height, rotation) = (self.width, self.height, self.rotation);
+        let offset = (self.first_row * width) as usize;
+        for Pixel(point, color) in item_pixels {
+            if outside_display(point.x as u32, point.y as u32, width, height, rotation) {
+                continue;
+            }
+
+            let index = find_position(point.x as u32, point.y as u32, width, height, rotation);
+            // Skip pixels outside of the rows held in the buffer
+            if let Some(elem) = index
+                .checked_sub(offset)
+                .and_then(|index| self.buffer.get_mut(index))
+            {
+                *elem = color;
+                self.dirty
+                    .add_pixel(index as u32 % width, index as u32 / width);
+            }
+        }
     }
 }
 
 impl<'a> Display for VarDisplay<'a> {
+    type Buffer = [EpdColor];
+
     fn buffer(&self) -> &[EpdColor] {
         &self.buffer
     }
@@ -127,6 +404,114 @@ impl<'a> Display for VarDisplay<'a> {
         &mut self.buffer
     }
 
+    fn clear_buffer(&mut self, background_color: EpdColor) {
+        self.buffer.fill(background_color);
+        // only the rows of the strip changed, none if the buffer is shorter than a row
+        if self.rows() > 0 {
+            let last_row = self.first_row + self.rows() - 1;
+            self.dirty
+                .add(Region::new(0, self.first_row, self.width - 1, last_row));
+        }
+    }
+
+    fn dirty_tracker(&mut self) -> Option<&mut DirtyRegions> {
+        Some(&mut self.dirty)
+    }
+
+    fn set_rotation(&mut self, rotation: DisplayRotation) {
+        self.rotation = rotation;
+    }
+
+    fn rotation(&self) -> DisplayRotation {
+        self.rotation
+    }
+}
+
+impl<'a> DirtyTracking for VarDisplay<'a> {
+    fn dirty(&self) -> &DirtyRegions {
+        &self.dirty
+    }
+
+    fn get_mut_dirty(&mut self) -> &mut DirtyRegions {
+        &mut self.dirty
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+
+    fn first_row(&self) -> u32 {
+        self.first_row
+    }
+}
+
+/// A variable Display storing 4 pixels per byte
+///
+/// Needs only a quarter of the memory of [VarDisplay]. The buffer can be created as following:
+/// buffer: [DEFAULT_BACKGROUND_COLOR.get_byte_value(); WIDTH / 4 * HEIGHT]
+///
+/// Example:
+/// ```rust,no_run
+/// # use epd_waveshare_uart::epd4in3::{DEFAULT_BACKGROUND_COLOR, HEIGHT, WIDTH};
+/// # use epd_waveshare_uart::prelude::*;
+/// # use epd_waveshare_uart::graphics::PackedDisplay;
+/// # use embedded_graphics::prelude::*;
+/// # use embedded_graphics::primitives::Line;
+/// let mut buffer = [DEFAULT_BACKGROUND_COLOR.get_byte_value(); 800 / 4 * 600];
+/// let mut display = PackedDisplay::new(WIDTH, HEIGHT, &mut buffer);
+///
+/// display.draw(
+///     Line::new(Point::new(0, 120), Point::new(0, 295))
+///         .stroke(Some(EpdColor::Black))
+///         .into_iter(),
+/// );
+/// ```
+pub struct PackedDisplay<'a> {
+    width: u32,
+    height: u32,
+    rotation: DisplayRotation,
+    buffer: &'a mut [u8],
+    dirty: DirtyRegions,
+}
+
+impl<'a> PackedDisplay<'a> {
+    pub fn new(width: u32, height: u32, buffer: &'a mut [u8]) -> PackedDisplay<'a> {
+        let len = buffer.len() as u32;
+        assert!(len * PIXELS_PER_BYTE as u32 >= width * height);
+        PackedDisplay {
+            width,
+            height,
+            rotation: DisplayRotation::default(),
+            buffer,
+            dirty: DirtyRegions::new(width, height),
+        }
+    }
+}
+
+impl<'a> Drawing<EpdColor> for PackedDisplay<'a> {
+    fn draw<T>(&mut self, item_pixels: T)
+    where
+        T: IntoIterator<Item = Pixel<EpdColor>>,
+    {
+        self.draw_helper(self.width, self.height, item_pixels);
+    }
+}
+
+impl<'a> Display for PackedDisplay<'a> {
+    type Buffer = [u8];
+
+    fn buffer(&self) -> &[u8] {
+        self.buffer
+    }
+
+    fn get_mut_buffer(&mut self) -> &mut [u8] {
+        self.buffer
+    }
+
+    fn dirty_tracker(&mut self) -> Option<&mut DirtyRegions> {
+        Some(&mut self.dirty)
+    }
+
     fn set_rotation(&mut self, rotation: DisplayRotation) {
         self.rotation = rotation;
     }
@@ -136,6 +521,20 @@ impl<'a> Display for VarDisplay<'a> {
     }
 }
 
+impl<'a> DirtyTracking for PackedDisplay<'a> {
+    fn dirty(&self) -> &DirtyRegions {
+        &self.dirty
+    }
+
+    fn get_mut_dirty(&mut self) -> &mut DirtyRegions {
+        &mut self.dirty
+    }
+
+    fn width(&self) -> u32 {
+        self.width
+    }
+}
+
 // Checks if a pos is outside the defined display
 fn outside_display(x: u32, y: u32, width: u32, height: u32, rotation: DisplayRotation) -> bool {
     match rotation {
@@ -167,14 +566,17 @@ fn find_position(x: u32, y: u32, width: u32, height: u32, rotation: DisplayRotat
             ((width  * height - 1) - (x  + (width  * y))) as usize,
         
         DisplayRotation::Rotate270 => 
-            (y  + height - 1 - (x * width)) as usize,
+            (y  + width * (height - 1 - x)) as usize,
         
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{find_position, outside_display, Display, DisplayRotation, VarDisplay};
+    use super::{
+        find_position, outside_display, DirtyRegions, DirtyTracking, Display, DisplayRotation,
+        PackedDisplay, PixelBuffer, Region, VarDisplay,
+    };
     use crate::color::EpdColor;
     use embedded_graphics::geometry::Point;
     use embedded_graphics::prelude::*;
@@ -203,7 +605,41 @@ mod tests {
         use crate::epd4in3::{HEIGHT, WIDTH};
         let width = WIDTH as u32;
         let height = HEIGHT as u32;
-        test_rotation_overflow(width, height, DisplayRotation::Rotate0);    
+        test_rotation_overflow(width, height, DisplayRotation::Rotate0);
+        test_rotation_overflow(width, height, DisplayRotation::Rotate90);
+        test_rotation_overflow(width, height, DisplayRotation::Rotate180);
+        test_rotation_overflow(width, height, DisplayRotation::Rotate270);
+    }
+
+    #[test]
+    fn rotation_corners() {
+        let (width, height) = (8, 4);
+        let last = (width * height - 1) as usize;
+        // the origin of each rotation ends up in a different corner of the buffer
+        assert_eq!(
+            find_position(0, 0, width, height, DisplayRotation::Rotate0),
+            0
+        );
+        assert_eq!(
+            find_position(0, 0, width, height, DisplayRotation::Rotate90),
+            7
+        );
+        assert_eq!(
+            find_position(0, 0, width, height, DisplayRotation::Rotate180),
+            last
+        );
+        assert_eq!(
+            find_position(0, 0, width, height, DisplayRotation::Rotate270),
+            24
+        );
+        assert_eq!(
+            find_position(3, 7, width, height, DisplayRotation::Rotate90),
+            24
+        );
+        assert_eq!(
+            find_position(3, 7, width, height, DisplayRotation::Rotate270),
+            7
+        );
     }
 
     fn test_rotation_overflow(width: u32, height: u32, rotation2: DisplayRotation) {
@@ -221,6 +657,183 @@ mod tests {
         }
     }
 
+    #[test]
+    fn packed_rotations() {
+        use crate::epd4in3::DEFAULT_BACKGROUND_COLOR;
+        let (width, height) = (16, 8);
+        let rotations = [
+            (DisplayRotation::Rotate0, Point::new(1, 0), 1),
+            (DisplayRotation::Rotate90, Point::new(0, 1), 14),
+            (DisplayRotation::Rotate180, Point::new(1, 0), 126),
+            (DisplayRotation::Rotate270, Point::new(0, 1), 113),
+        ];
+
+        for &(rotation, point, index) in rotations.iter() {
+            let mut buffer = [DEFAULT_BACKGROUND_COLOR.get_byte_value(); 16 / 4 * 8];
+            let mut display = PackedDisplay::new(width, height, &mut buffer);
+            display.set_rotation(rotation);
+            display.draw(Some(Pixel(point, EpdColor::DarkGray)));
+
+            let buffer = display.buffer();
+            for i in 0..(width * height) as usize {
+                if i == index {
+                    assert_eq!(buffer.pixel(i), EpdColor::DarkGray);
+                } else {
+                    assert_eq!(buffer.pixel(i), DEFAULT_BACKGROUND_COLOR);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packed_buffer_clear() {
+        let mut buffer = [0u8; 16 / 4 * 8];
+        let mut display = PackedDisplay::new(16, 8, &mut buffer);
+
+        display.clear_buffer(EpdColor::Gray);
+
+        for &byte in display.buffer.iter() {
+            assert_eq!(byte, EpdColor::Gray.get_byte_value());
+        }
+    }
+
+    #[test]
+    fn dirty_regions_merge() {
+        let mut dirty = DirtyRegions::new(100, 100);
+        dirty.add_pixel(10, 10);
+        dirty.add_pixel(11, 10);
+        dirty.add_pixel(12, 11);
+        dirty.add_pixel(50, 50);
+        assert_eq!(
+            dirty.regions(),
+            [Region::new(10, 10, 12, 11), Region::new(50, 50, 50, 50)]
+        );
+
+        dirty.set_limit(1);
+        assert_eq!(dirty.regions(), [Region::new(10, 10, 50, 50)]);
+
+        assert_eq!(dirty.take().len(), 1);
+        assert!(dirty.regions().is_empty());
+    }
+
+    #[test]
+    fn dirty_regions_limit() {
+        let mut dirty = DirtyRegions::new(100, 100);
+        dirty.set_limit(2);
+        dirty.add_pixel(0, 0);
+        dirty.add_pixel(90, 90);
+        dirty.add_pixel(0, 3);
+        assert_eq!(
+            dirty.regions(),
+            [Region::new(90, 90, 90, 90), Region::new(0, 0, 0, 3)]
+        );
+    }
+
+    #[test]
+    fn draw_marks_dirty() {
+        let mut buffer = [EpdColor::White; 16 * 8];
+        let mut display = VarDisplay::new(16, 8, &mut buffer);
+        assert!(display.dirty_regions().is_empty());
+
+        display.set_rotation(DisplayRotation::Rotate180);
+        display.draw(Line::new(Point::new(0, 0), Point::new(3, 0)).stroke(Some(EpdColor::Black)));
+        assert_eq!(display.take_dirty().as_slice(), [Region::new(12, 7, 15, 7)]);
+
+        display.clear_buffer(EpdColor::White);
+        assert_eq!(display.dirty_regions(), [Region::new(0, 0, 15, 7)]);
+    }
+
+    #[test]
+    fn strip_clips_to_rows() {
+        let mut buffer = [EpdColor::White; 16 * 2];
+        let mut display = VarDisplay::strip(16, 8, 3, &mut buffer);
+        assert_eq!(display.rows(), 2);
+
+        display.draw(Line::new(Point::new(2, 0), Point::new(2, 7)).stroke(Some(EpdColor::Black)));
+
+        let buffer = display.buffer();
+        for (index, &color) in buffer.iter().enumerate() {
+            if index % 16 == 2 {
+                assert_eq!(color, EpdColor::Black);
+            } else {
+                assert_eq!(color, EpdColor::White);
+            }
+        }
+    }
+
+    // display implementing only the required methods, like one outside of this crate
+    struct PlainDisplay {
+        buffer: [EpdColor; 16],
+    }
+
+    impl Drawing<EpdColor> for PlainDisplay {
+        fn draw<T>(&mut self, item_pixels: T)
+        where
+            T: IntoIterator<Item = Pixel<EpdColor>>,
+        {
+            self.draw_helper(4, 4, item_pixels);
+        }
+    }
+
+    impl Display for PlainDisplay {
+        type Buffer = [EpdColor];
+
+        fn buffer(&self) -> &[EpdColor] {
+            &self.buffer
+        }
+
+        fn get_mut_buffer(&mut self) -> &mut [EpdColor] {
+            &mut self.buffer
+        }
+
+        fn set_rotation(&mut self, _rotation: DisplayRotation) {}
+
+        fn rotation(&self) -> DisplayRotation {
+            DisplayRotation::Rotate0
+        }
+    }
+
+    #[test]
+    fn draws_without_tracking() {
+        let mut display = PlainDisplay {
+            buffer: [EpdColor::White; 16],
+        };
+        display.draw(Some(Pixel(Point::new(1, 2), EpdColor::Black)));
+        assert_eq!(display.buffer()[9], EpdColor::Black);
+
+        display.clear_buffer(EpdColor::Gray);
+        assert!(display
+            .buffer()
+            .iter()
+            .all(|&color| color == EpdColor::Gray));
+    }
+
+    #[test]
+    fn clear_short_buffer() {
+        let mut buffer = [EpdColor::Black; 10];
+        let mut display = VarDisplay::new(16, 8, &mut buffer);
+        assert_eq!(display.rows(), 0);
+
+        display.clear_buffer(EpdColor::White);
+        assert!(display.dirty_regions().is_empty());
+        assert!(display.buffer().iter().all(|&c| c == EpdColor::White));
+    }
+
+    #[test]
+    fn strip_rotated() {
+        let mut buffer = [EpdColor::White; 16 * 2];
+        let mut display = VarDisplay::strip(16, 8, 6, &mut buffer);
+        display.set_rotation(DisplayRotation::Rotate180);
+
+        // row 0 and 1 of the rotated display are the last two rows of the buffer
+        display.draw(Some(Pixel(Point::new(0, 0), EpdColor::Black)));
+        display.draw(Some(Pixel(Point::new(0, 2), EpdColor::Black)));
+
+        let buffer = display.buffer();
+        assert_eq!(buffer[31], EpdColor::Black);
+        assert_eq!(buffer.iter().filter(|&&c| c == EpdColor::Black).count(), 1);
+    }
+
     #[test]
     fn graphics_rotation_0() {
         use crate::epd4in3::DEFAULT_BACKGROUND_COLOR;
@@ -1,3 +1,5 @@
+use crate::command::{self, FrameError};
+use crate::epd4in3::protocol::ReplyMatcher;
 use crate::traits::Error;
 use core::marker::PhantomData;
 use embedded_hal::{
@@ -11,34 +13,68 @@ use embedded_hal::{
 pub(crate) struct DisplayInterface<SERIAL, WAKE, RST> {
     /// SERIAL
     _serial: PhantomData<SERIAL>,
-    /// Pin for Wake-up
-    wake: WAKE,
-    /// Pin for Reseting
-    rst: RST,
+    /// Pin for Wake-up, if connected
+    wake: Option<WAKE>,
+    /// Pin for Reseting, if connected
+    rst: Option<RST>,
+    /// How long the WAKE and RST pins are held low and high, in ms
+    pulse_width_ms: u16,
+    /// How long the device may take to answer a handshake after a pulse, in ms
+    ready_timeout_ms: u32,
 }
 
-impl<E, F, G, SERIAL, WAKE, RST> DisplayInterface<SERIAL, WAKE, RST>
+/// Default for how long the WAKE and RST pins are held low and high
+pub(crate) const DEFAULT_PULSE_WIDTH_MS: u16 = 10;
+/// Default for how long the device may take to become ready
+pub(crate) const DEFAULT_READY_TIMEOUT_MS: u32 = 5000;
+/// Pause between two handshakes while waiting for the device
+const POLL_INTERVAL_MS: u16 = 20;
+
+impl<SERIAL, WAKE, RST> DisplayInterface<SERIAL, WAKE, RST> {
+    /// Returns the pins
+    pub(crate) fn release(self) -> (Option<WAKE>, Option<RST>) {
+        (self.wake, self.rst)
+    }
+}
+
+impl<E, F, G, H, SERIAL, WAKE, RST> DisplayInterface<SERIAL, WAKE, RST>
 where
     SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
     WAKE: OutputPin<Error = G>,
-    RST: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
 {
-    //type Error = Error<E, F, G>;
+    //type Error = Error<E, F, G, H>;
 
-    pub fn new(wake: WAKE, rst: RST) -> Self {
+    pub fn new(wake: Option<WAKE>, rst: Option<RST>) -> Self {
         DisplayInterface {
             _serial: PhantomData::default(),
             wake,
             rst,
+            pulse_width_ms: DEFAULT_PULSE_WIDTH_MS,
+            ready_timeout_ms: DEFAULT_READY_TIMEOUT_MS,
         }
     }
 
-    /// Basic function for sending an array of u8-values of data over serial
+    pub(crate) fn set_pulse_width(&mut self, ms: u16) {
+        self.pulse_width_ms = ms;
+    }
+
+    pub(crate) fn set_ready_timeout(&mut self, ms: u32) {
+        self.ready_timeout_ms = ms;
+    }
+
+    /// Writes a single command frame directly to serial
     ///
-    /// Enables direct interaction with the device
-    pub(crate) fn data(&mut self, serial: &mut SERIAL, data: &[u8]) -> Result<(), Error<E, F, G>> {
-        // Transfer data (u8-array) over serial
-        self.write(serial, data)
+    /// The frame is encoded while it is sent, see [command::FrameWriter](crate::command::FrameWriter)
+    pub(crate) fn command<C>(
+        &mut self,
+        serial: &mut SERIAL,
+        write: C,
+    ) -> Result<(), Error<E, F, G, H>>
+    where
+        C: FnOnce(&mut SERIAL) -> Result<(), FrameError<F>>,
+    {
+        write(serial).map_err(Error::from)
     }
 
     /// Basic function for reading an array of u8-values of data over serial
@@ -48,7 +84,7 @@ where
         &mut self,
         serial: &mut SERIAL,
         data: &mut [u8],
-    ) -> Result<(), Error<E, F, G>> {
+    ) -> Result<(), Error<E, F, G, H>> {
         // Read data (u8-array) over serial
         self.read(serial, data)
     }
@@ -58,7 +94,7 @@ where
         &mut self,
         serial: &mut SERIAL,
         data: &mut [u8],
-    ) -> Result<(), Error<E, F, G>> {
+    ) -> Result<(), Error<E, F, G, H>> {
         for item in data.iter_mut() {
             match serial.read() {
                 Ok(byte) => *item = byte,
@@ -71,50 +107,99 @@ where
         Ok(())
     }
 
-    // serial write helper/abstraction function
-    fn write(&mut self, serial: &mut SERIAL, data: &[u8]) -> Result<(), Error<E, F, G>> {
-        //extern crate std;
-        //std::println!("{:x?}", data);
-        serial.bwrite_all(data).map_err(Error::SerialW)
+    /// Sends handshakes until the device answers one
+    ///
+    /// Returns [Error::Timeout] if it didn't answer within the ready timeout. The answers are
+    /// polled every millisecond, so waiting for them counts towards it as well as the pauses
+    /// between the handshakes. Everything received after a failed handshake is dropped before
+    /// the next one is sent, so a late answer can't be taken for the answer to it.
+    pub(crate) fn wait_ready<DELAY: DelayMs<u16>>(
+        &mut self,
+        serial: &mut SERIAL,
+        delay: &mut DELAY,
+    ) -> Result<(), Error<E, F, G, H>> {
+        let mut waited_ms = 0;
+        loop {
+            self.command(serial, command::write_handshake)?;
+            let mut matcher = ReplyMatcher::default();
+            let reply = loop {
+                match serial.read() {
+                    Ok(byte) => {
+                        if let Some(ok) = matcher.push(byte) {
+                            break Some(ok);
+                        }
+                    }
+                    Err(nb::Error::WouldBlock) if waited_ms < self.ready_timeout_ms => {
+                        delay.delay_ms(1);
+                        waited_ms += 1;
+                    }
+                    Err(nb::Error::WouldBlock) => break None,
+                    Err(nb::Error::Other(e)) => return Err(Error::SerialR(e)),
+                }
+            };
+            if reply == Some(true) {
+                return Ok(());
+            }
+            if waited_ms >= self.ready_timeout_ms {
+                return Err(Error::Timeout);
+            }
+            delay.delay_ms(POLL_INTERVAL_MS);
+            waited_ms += u32::from(POLL_INTERVAL_MS);
+            self.discard_input(serial)?;
+        }
     }
 
-    /// Resets the device.
-    ///
-    /// Often used to awake the module from deep sleep. See [EPD4in3::sleep()](EPD4in3::sleep())
+    // Drops everything received until nothing is left to read
+    fn discard_input(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G, H>> {
+        loop {
+            match serial.read() {
+                Ok(_) => {}
+                Err(nb::Error::WouldBlock) => return Ok(()),
+                Err(nb::Error::Other(e)) => return Err(Error::SerialR(e)),
+            }
+        }
+    }
+
+    /// Resets the device and waits until it is ready.
     ///
-    /// TODO: Takes at least 400ms of delay alone, can it be shortened?
+    /// Often used to awake the module from deep sleep. See [EPD4in3::sleep()](crate::epd4in3::EPD4in3::sleep())
+    /// Without a RST pin it only waits for the device to answer a handshake.
     pub(crate) fn reset<DELAY: DelayMs<u16>>(
         &mut self,
+        serial: &mut SERIAL,
         delay: &mut DELAY,
-    ) -> Result<(), Error<E, F, G>> {
-        self.rst.set_low().map_err(Error::GpioE)?;
-        //TODO: why 200ms? (besides being in the arduino version)
-        delay.delay_ms(255);
-        self.rst.set_high().map_err(Error::GpioE)?;
-        //TODO: same as 3 lines above
-        delay.delay_ms(3000);
-        self.rst.set_low().map_err(Error::GpioE)?;
-        delay.delay_ms(255);
-        Ok(())
+    ) -> Result<(), Error<E, F, G, H>> {
+        if let Some(rst) = self.rst.as_mut() {
+            pulse(rst, delay, self.pulse_width_ms).map_err(Error::GpioRst)?;
+        }
+        self.wait_ready(serial, delay)
     }
 
-    /// Wakes the device.
+    /// Wakes the device and waits until it is ready.
     ///
-    /// Often used to awake the module from deep sleep. See [EPD4in3::sleep()](EPD4in3::sleep())
-    ///
-    /// TODO: Takes at least 400ms of delay alone, can it be shortened?
+    /// Often used to awake the module from deep sleep. See [EPD4in3::sleep()](crate::epd4in3::EPD4in3::sleep())
+    /// Without a WAKE pin it only waits for the device to answer a handshake.
     pub(crate) fn wake<DELAY: DelayMs<u16>>(
         &mut self,
+        serial: &mut SERIAL,
         delay: &mut DELAY,
-    ) -> Result<(), Error<E, F, G>> {
-        self.wake.set_low().map_err(Error::GpioE)?;
-        //TODO: why 200ms? (besides being in the arduino version)
-        delay.delay_ms(255);
-        self.wake.set_high().map_err(Error::GpioE)?;
-        //TODO: same as 3 lines above
-        delay.delay_ms(255);
-        self.wake.set_low().map_err(Error::GpioE)?;
-        delay.delay_ms(255);
-        Ok(())
+    ) -> Result<(), Error<E, F, G, H>> {
+        if let Some(wake) = self.wake.as_mut() {
+            pulse(wake, delay, self.pulse_width_ms).map_err(Error::GpioE)?;
+        }
+        self.wait_ready(serial, delay)
     }
 }
+
+// Drives a pin low, high and low again, keeping each level for `width_ms`
+fn pulse<P: OutputPin, DELAY: DelayMs<u16>>(
+    pin: &mut P,
+    delay: &mut DELAY,
+    width_ms: u16,
+) -> Result<(), P::Error> {
+    pin.set_low()?;
+    delay.delay_ms(width_ms);
+    pin.set_high()?;
+    delay.delay_ms(width_ms);
+    pin.set_low()
+}
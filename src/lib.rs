@@ -13,8 +13,9 @@
 //!
 //! ### Other....
 //!
-//! - Buffersize: Wherever a buffer is used it always needs to be of the size: `width / 8 * length`,
-//!   where width and length being either the full e-ink size or the partial update window size
+//! - Buffersize: Wherever a buffer is used it always needs to be of the size: `width * length`,
+//!   where width and length being either the full e-ink size or the partial update window size.
+//!   Packed buffers with 4 pixels per byte (see [graphics::PackedDisplay]) need `width / 4 * length` bytes
 //!
 //! # Examples
 //!
@@ -46,20 +47,28 @@
 //! epd.display_frame(&mut serial).expect("display frame new graphics");
 //!
 //! // Set the EPD to sleep
-//! epd.sleep(&mut serial).expect("sleep");
+//! let sleeping = epd.sleep(&mut serial).map_err(|(_, e)| e).expect("sleep");
 //! ```
 //!
 //!
 #![no_std]
 
+#[cfg(feature = "std")]
+extern crate std;
+
 #[cfg(feature = "graphics")]
 pub mod graphics;
 
+pub mod adapter;
 pub mod color;
 /// Interface for the physical connection between display and the controlling device
+#[cfg(feature = "epd4in3")]
 mod interface;
 mod traits;
 
+#[cfg(all(test, feature = "epd4in3"))]
+mod mock;
+
 #[cfg(feature = "epd4in3")]
 pub mod epd4in3;
 #[cfg(feature = "epd4in3")]
@@ -70,5 +79,5 @@ pub mod prelude {
     pub use crate::traits::WaveshareDisplay;
 
     #[cfg(feature = "graphics")]
-    pub use crate::graphics::{Display, DisplayRotation};
+    pub use crate::graphics::{DirtyTracking, Display, DisplayRotation};
 }
@@ -0,0 +1,102 @@
+//! Mocks of the embedded-hal traits used by the tests
+
+extern crate std;
+
+use core::convert::Infallible;
+use embedded_hal::{
+    blocking::{delay::DelayMs, serial},
+    digital::v2::OutputPin,
+    serial::Read,
+};
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use crate::epd4in3::protocol::ReplyMatcher;
+
+/// End of every frame, followed by the parity
+const FRAME_END: [u8; 4] = [0xCC, 0x33, 0xC3, 0x3C];
+
+/// Serial port recording everything written to it
+///
+/// Reads are answered from `replies` first. Afterwards every frame written is answered with
+/// `OK`, or never if `silent` is set.
+#[derive(Default)]
+pub(crate) struct MockSerial {
+    pub written: Vec<u8>,
+    pub replies: VecDeque<u8>,
+    pub read: usize,
+    pub silent: bool,
+    unanswered: usize,
+    answer: ReplyMatcher,
+    ok_index: usize,
+}
+
+impl serial::write::Default<u8> for MockSerial {}
+
+impl embedded_hal::serial::Write<u8> for MockSerial {
+    type Error = Infallible;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        self.written.push(word);
+        if self.written.ends_with(&FRAME_END) {
+            self.unanswered += 1;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl Read<u8> for MockSerial {
+    type Error = Infallible;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        let byte = match self.replies.pop_front() {
+            Some(byte) => byte,
+            None if self.silent || self.unanswered == 0 => return Err(nb::Error::WouldBlock),
+            None => {
+                self.ok_index ^= 1;
+                b"OK"[self.ok_index ^ 1]
+            }
+        };
+        self.read += 1;
+        if self.answer.push(byte).is_some() {
+            self.unanswered = self.unanswered.saturating_sub(1);
+        }
+        Ok(byte)
+    }
+}
+
+/// Output pin remembering its last state
+#[derive(Default)]
+pub(crate) struct MockPin {
+    pub high: bool,
+}
+
+impl OutputPin for MockPin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.high = false;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.high = true;
+        Ok(())
+    }
+}
+
+/// Delay which only sums up the requested time
+#[derive(Default)]
+pub(crate) struct MockDelay {
+    pub total_ms: u32,
+}
+
+impl DelayMs<u16> for MockDelay {
+    fn delay_ms(&mut self, ms: u16) {
+        self.total_ms += u32::from(ms);
+    }
+}
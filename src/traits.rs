@@ -13,23 +13,32 @@ pub(crate) trait Command {
 }
 
 /// Errors
+///
+/// The WAKE and RST pins may have different error types, by default they are the same
 #[derive(Debug)]
-pub enum Error<E, F, G> {
+pub enum Error<E, F, G, H = G> {
     /// Serial read bus error
     SerialR(E),
     /// Serial write error
     SerialW(F),
-    /// GPIO Error
+    /// Error of the WAKE pin
     GpioE(G),
-    /// Timeout
+    /// Error of the RST pin
+    GpioRst(H),
+    /// Timeout, e.g. the module didn't answer a handshake
     Timeout,
+    /// A command couldn't be encoded into a frame
+    Frame,
+    /// The sleep command was passed along with other commands, the display has to be put to sleep
+    /// with `sleep()` which returns the sleeping handle
+    Sleep,
 }
 
-pub(crate) trait InternalWiAdditions<E, F, G, SERIAL, WAKE, RST>
+pub(crate) trait InternalWiAdditions<E, F, G, SERIAL, WAKE, RST, H = G>
 where
     SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
     WAKE: OutputPin<Error = G>,
-    RST: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
 {
     /// This initialises the EPD and powers it up
     ///
@@ -45,23 +54,23 @@ where
         &mut self,
         serial: &mut SERIAL,
         delay: &mut DELAY,
-    ) -> Result<(), Error<E, F, G>>;
+    ) -> Result<(), Error<E, F, G, H>>;
 
     fn wake<DELAY: DelayMs<u16>>(
         &mut self,
         serial: &mut SERIAL,
         delay: &mut DELAY,
-    ) -> Result<(), Error<E, F, G>>;
+    ) -> Result<(), Error<E, F, G, H>>;
 }
 
 /// All the functions to interact with the EPDs
 ///
 /// This trait includes all public functions to use the EPDS
-pub trait WaveshareDisplay<E, F, G, SERIAL, WAKE, RST>
+pub trait WaveshareDisplay<E, F, G, SERIAL, WAKE, RST, H = G>
 where
     SERIAL: Write<u8, Error = F> + Read<u8, Error = E>,
     WAKE: OutputPin<Error = G>,
-    RST: OutputPin<Error = G>,
+    RST: OutputPin<Error = H>,
 {
     /// Creates a new driver from a Serial peripheral, Wake Pin, Reset Pin
     ///
@@ -71,24 +80,16 @@ where
         wake: WAKE,
         rst: RST,
         delay: &mut DELAY,
-    ) -> Result<Self, Error<E, F, G>>
+    ) -> Result<Self, Error<E, F, G, H>>
     where
         Self: Sized;
 
-    /// Let the device enter deep-sleep mode to save power.
-    ///
-    /// The deep sleep mode returns to standby with a hardware reset.
-    /// But you can also use [wake_up()](WaveshareInterface::wake_up()) to awaken.
-    /// But as you need to power it up once more anyway you can also just directly use [new()](WaveshareInterface::new()) for resetting
-    /// and initialising which already contains the reset
-    fn sleep(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G>>;
-
     /// Wakes the device up from sleep
     fn wake_up<DELAY: DelayMs<u16>>(
         &mut self,
         serial: &mut SERIAL,
         delay: &mut DELAY,
-    ) -> Result<(), Error<E, F, G>>;
+    ) -> Result<(), Error<E, F, G, H>>;
 
     /// Sets the backgroundcolor for various commands like [clear_frame()](WaveshareInterface::clear_frame())
     fn set_background_color(&mut self, color: EpdColor);
@@ -108,13 +109,13 @@ where
         serial: &mut SERIAL,
         buffer: &[EpdColor],
         delay: &mut DELAY,
-    ) -> Result<(), Error<E, F, G>>;
+    ) -> Result<(), Error<E, F, G, H>>;
 
     /// Displays the frame data from SRAM
-    fn display_frame(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G>>;
+    fn display_frame(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G, H>>;
 
     /// Clears the frame buffer on the EPD with the declared background color
     ///
     /// The background color can be changed with [`set_background_color`]
-    fn clear_frame(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G>>;
+    fn clear_frame(&mut self, serial: &mut SERIAL) -> Result<(), Error<E, F, G, H>>;
 }